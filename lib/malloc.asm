@@ -0,0 +1,111 @@
+// malloc and free on top of the brk and sbrk sys calls at 16 and 17.
+//
+// Append this file to a program to use it. Arguments are pushed on the stack
+// and the result is returned in eax, eax to edx and %9 to %11 are clobbered.
+//
+// Every block starts with a header word holding the size of the block, without
+// the header, with bit 0 set while the block is in use. Blocks are reused
+// first fit and never split or merged.
+
+// malloc(size) -> ptr, returns 0 if the heap can't grow
+malloc:
+	pop %9
+	push erp
+
+	// round the size up to a multiple of 4
+	const 3u %10
+	addi %9 %10 %9
+	not %10 %10
+	and %9 %10 %9
+
+	// query the break to eax and the start of the heap to ebx
+	const 0u eax
+	const 16u edx
+	call edx
+
+malloc::loop:
+	// grow the heap when every block is in use
+	const malloc::grow %10
+	addi ebp %10 %10
+	beq %10 ebx eax
+
+	// read the header
+	load ebx ecx 4
+	const 1u %10
+	and ecx %10 %11
+	not %10 %10
+	and ecx %10 ecx
+
+	// skip blocks that are in use or too small
+	const malloc::next %10
+	addi ebp %10 %10
+	jmpnz %10 %11
+	bltu %10 ecx %9
+
+	// mark the block as used
+	const 1u %10
+	or ecx %10 ecx
+	store ecx ebx 4
+
+	const 4u %10
+	addi ebx %10 eax
+
+	pop erp
+	ret
+
+malloc::next:
+	const 4u %10
+	addi ebx %10 ebx
+	addi ebx ecx ebx
+
+	const malloc::loop %10
+	addi ebp %10 %10
+	jmp %10
+
+malloc::grow:
+	// allocate the header and the block
+	const 4u %10
+	addi %9 %10 eax
+	const 17u edx
+	call edx
+
+	const malloc::fail %10
+	addi ebp %10 %10
+	const -1i %11
+	beq %10 eax %11
+
+	const 1u %10
+	or %9 %10 %10
+	store %10 eax 4
+
+	const 4u %10
+	addi eax %10 eax
+
+	pop erp
+	ret
+
+malloc::fail:
+	const 0u eax
+
+	pop erp
+	ret
+
+// free(ptr), does nothing if ptr is 0
+free:
+	pop eax
+
+	const free::end %10
+	addi ebp %10 %10
+	jmpz %10 eax
+
+	// clear the used bit of the header
+	const 4u %10
+	subi eax %10 eax
+	load eax ecx 4
+	const 1u %10
+	not %10 %10
+	and ecx %10 ecx
+	store ecx eax 4
+
+free::end:
+	ret
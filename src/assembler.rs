@@ -1,23 +1,44 @@
 use std::{borrow::Cow, collections::HashMap};
 
-use crate::{Arg, Args, Instruction, Label, Opcode, Program, Register, Word};
+use crate::{instruction::Operand, Args, Instruction, Label, Opcode, Program, Register, Word};
 
 #[derive(Clone, Debug)]
 pub struct AssemblerError {
     message: Cow<'static, str>,
+    line: Option<usize>,
 }
 
 impl AssemblerError {
     pub fn new(msg: impl Into<Cow<'static, str>>) -> Self {
         Self {
             message: msg.into(),
+            line: None,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the line of the source the error is on, starting at 1.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    fn at(self, line: usize) -> Self {
+        Self {
+            line: Some(line),
+            ..self
         }
     }
 }
 
 impl std::fmt::Display for AssemblerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.message)
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
     }
 }
 
@@ -34,7 +55,15 @@ pub enum Constant {
 pub enum Line {
     Comment(String),
     Label(Label),
-    Constant { constant: Constant, dst: Register },
+    Constant {
+        constant: Constant,
+        dst: Register,
+    },
+    /// A `memcmp`, the destination is in the word after the instruction.
+    Compare {
+        ins: Instruction,
+        dst: Register,
+    },
     Instruction(Instruction),
 }
 
@@ -125,6 +154,7 @@ fn parse_register(src: &str) -> Result<Register, AssemblerError> {
         "erp" => return Ok(Register::ERP),
         "ebp" => return Ok(Register::EBP),
         "exp" => return Ok(Register::EXP),
+        "ecf" => return Ok(Register::ECF),
         _ => {}
     }
 
@@ -140,10 +170,13 @@ fn parse_register(src: &str) -> Result<Register, AssemblerError> {
 }
 
 fn parse_width(src: &str) -> Result<u8, AssemblerError> {
-    if let Ok(width) = src.parse::<u8>() {
-        Ok(width)
-    } else {
-        Err(AssemblerError::new("expected u8"))
+    match src.parse::<u8>() {
+        Ok(width @ (1 | 2 | 4)) => Ok(width),
+        Ok(width) => Err(AssemblerError::new(format!(
+            "invalid width {}, expected 1, 2 or 4",
+            width
+        ))),
+        Err(_) => Err(AssemblerError::new("expected u8")),
     }
 }
 
@@ -168,79 +201,45 @@ fn parse_constant(src: &str) -> Result<Constant, AssemblerError> {
     }
 }
 
-#[allow(unused_assignments)]
 fn parse_instruction(instruction: &str, args: &[&str]) -> Result<Line, AssemblerError> {
-    macro_rules! ins {
-        ($opcode:ident, [$($arg:tt),*]) => {
-            Instruction {
-                opcode: Opcode::$opcode,
-                args: {
-                    #[allow(unused)]
-                    let mut arg = 0usize;
-
-                    Args::from(($({
-                        let res = match stringify!($arg) {
-                            "reg" => Arg::from(parse_register(self::arg(args, arg)?)?),
-                            "width" => Arg::from(parse_width(self::arg(args, arg)?)?),
-                            _ => panic!(),
-                        };
-
-                        arg += 1;
-
-                        res
-                    },)*))
-                },
-            }
+    if instruction == "const" {
+        return Ok(Line::Constant {
+            constant: parse_constant(arg(args, 0)?)?,
+            dst: parse_register(arg(args, 1)?)?,
+        });
+    }
+
+    // `shift` is the old name of `shr`
+    let mnemonic = match instruction {
+        "shift" => "shr",
+        mnemonic => mnemonic,
+    };
+
+    let (opcode, operands) = Opcode::from_mnemonic(mnemonic)
+        .ok_or_else(|| AssemblerError::new(format!("invalid instruction {}", instruction)))?;
+
+    let mut bytes = [0; 3];
+
+    for (index, operand) in operands.iter().enumerate() {
+        bytes[index] = match operand {
+            Operand::Reg => parse_register(arg(args, index)?)?.0,
+            Operand::Width => parse_width(arg(args, index)?)?,
         };
     }
 
-    Ok(Line::Instruction(match instruction {
-        "const" => {
-            return Ok(Line::Constant {
-                constant: parse_constant(arg(args, 0)?)?,
-                dst: parse_register(arg(args, 1)?)?,
-            });
-        }
+    let ins = Instruction {
+        opcode,
+        args: Args::from_bytes(bytes),
+    };
 
-        "mov" => ins!(MOV, [reg, reg]),
-        "push" => ins!(PUSH, [reg]),
-        "pop" => ins!(POP, [reg]),
-        "load" => ins!(LOAD, [reg, reg, width]),
-        "store" => ins!(STORE, [reg, reg, width]),
-
-        "jmp" => ins!(JMP, [reg]),
-        "jmpnz" => ins!(JMP_NZ, [reg, reg]),
-        "call" => ins!(CALL, [reg]),
-        "ret" => ins!(RET, []),
-        "exit" => ins!(EXIT, [reg]),
-
-        "addi" => ins!(ADDI, [reg, reg, reg]),
-        "subi" => ins!(SUBI, [reg, reg, reg]),
-        "muli" => ins!(MULI, [reg, reg, reg]),
-        "divi" => ins!(DIVI, [reg, reg, reg]),
-        "modi" => ins!(MODI, [reg, reg, reg]),
-        "gti" => ins!(GTI, [reg, reg, reg]),
-        "lti" => ins!(LTI, [reg, reg, reg]),
-
-        "shift" => ins!(SHIFT, [reg, reg, reg]),
-        "and" => ins!(AND, [reg, reg, reg]),
-        "or" => ins!(OR, [reg, reg, reg]),
-        "xor" => ins!(XOR, [reg, reg, reg]),
-        "eq" => ins!(EQ, [reg, reg, reg]),
-
-        "addf" => ins!(ADDF, [reg, reg, reg]),
-        "subf" => ins!(SUBF, [reg, reg, reg]),
-        "mulf" => ins!(MULF, [reg, reg, reg]),
-        "divf" => ins!(DIVF, [reg, reg, reg]),
-        "modf" => ins!(MODF, [reg, reg, reg]),
-        "floorf" => ins!(FLOORF, [reg, reg]),
-        _ => {
-            return Err(AssemblerError::new(format!(
-                "invalid instruction {}",
-                instruction
-            )))
-        }
-    }))
+    if opcode == Opcode::MEMCMP {
+        return Ok(Line::Compare {
+            ins,
+            dst: parse_register(arg(args, 3)?)?,
+        });
+    }
+
+    Ok(Line::Instruction(ins))
 }
 
 fn parse_line(line: &str) -> Result<Line, AssemblerError> {
@@ -251,7 +250,7 @@ fn parse_line(line: &str) -> Result<Line, AssemblerError> {
     if let Some(label) = line.strip_suffix(':') {
         Ok(Line::Label(parse_label(label)?))
     } else {
-        let mut parts = line.trim().split_whitespace();
+        let mut parts = line.split_whitespace();
 
         if let Some(instruction) = parts.next() {
             let args = parts.collect::<Vec<_>>();
@@ -263,38 +262,60 @@ fn parse_line(line: &str) -> Result<Line, AssemblerError> {
     }
 }
 
-pub fn parse_file(source: &str) -> Result<Vec<Line>, AssemblerError> {
+/// Parses the non-empty lines of `source` with their line numbers.
+fn parse_numbered(source: &str) -> Result<Vec<(usize, Line)>, AssemblerError> {
     let mut lines = Vec::new();
 
-    for line in source.trim().split('\n') {
+    for (index, line) in source.lines().enumerate() {
         if !line.trim().is_empty() {
-            lines.push(parse_line(line.trim())?);
+            let line = parse_line(line.trim()).map_err(|error| error.at(index + 1))?;
+            lines.push((index + 1, line));
         }
     }
 
     Ok(lines)
 }
 
+pub fn parse_file(source: &str) -> Result<Vec<Line>, AssemblerError> {
+    let lines = parse_numbered(source)?;
+    Ok(lines.into_iter().map(|(_, line)| line).collect())
+}
+
+/// Parses and assembles `source`, errors have the line they're on.
+pub fn assemble(source: &str) -> Result<Program, AssemblerError> {
+    let (numbers, lines): (Vec<_>, Vec<_>) = parse_numbered(source)?.into_iter().unzip();
+
+    assemble_indexed(lines).map_err(|(index, error)| error.at(numbers[index]))
+}
+
 pub(crate) const fn align(ptr: u32, align: u32) -> u32 {
-    (ptr - 1) / align * align + align
+    ptr.div_ceil(align) * align
 }
 
 pub fn assemble_lines(lines: Vec<Line>) -> Result<Program, AssemblerError> {
+    assemble_indexed(lines).map_err(|(_, error)| error)
+}
+
+/// Assembles `lines`, errors have the index of the line they're on.
+fn assemble_indexed(lines: Vec<Line>) -> Result<Program, (usize, AssemblerError)> {
     let mut labels = HashMap::new();
 
     let mut ins_offset = 0;
 
-    for line in &lines {
+    for (index, line) in lines.iter().enumerate() {
         match line {
             Line::Label(label) => {
-                if labels.insert(label.clone(), ins_offset).is_some() {
-                    return Err(AssemblerError::new(format!(
-                        "duplicate label '{}'",
-                        label.0
-                    )));
+                if let Some(previous) = labels.insert(label.clone(), ins_offset) {
+                    return Err((
+                        index,
+                        AssemblerError::new(format!(
+                            "duplicate label '{}', already defined at offset {}",
+                            label.0, previous
+                        )),
+                    ));
                 }
             }
-            Line::Constant { .. } => {
+            Line::Constant { .. } | Line::Compare { .. } => {
                 ins_offset += 8;
             }
             Line::Instruction(_) => ins_offset += 4,
@@ -305,7 +326,7 @@ pub fn assemble_lines(lines: Vec<Line>) -> Result<Program, AssemblerError> {
     let mut const_offset = 0;
     let mut program = Program::new();
 
-    for line in &lines {
+    for (index, line) in lines.iter().enumerate() {
         match line {
             Line::Constant { constant, dst } => {
                 let ins = Instruction {
@@ -318,10 +339,10 @@ pub fn assemble_lines(lines: Vec<Line>) -> Result<Program, AssemblerError> {
                         if let Some(offset) = labels.get(label) {
                             Word::from_u32(*offset)
                         } else {
-                            return Err(AssemblerError::new(format!(
-                                "undefined label '{}'",
-                                label.0
-                            )));
+                            return Err((
+                                index,
+                                AssemblerError::new(format!("undefined label '{}'", label.0)),
+                            ));
                         }
                     }
                     Constant::String(string) => {
@@ -337,6 +358,10 @@ pub fn assemble_lines(lines: Vec<Line>) -> Result<Program, AssemblerError> {
                 program.push_instruction(ins);
                 program.push_word(data);
             }
+            &Line::Compare { ins, dst } => {
+                program.push_instruction(ins);
+                program.push_word(Word::from_bytes([dst.0, 0, 0, 0]));
+            }
             &Line::Instruction(ins) => {
                 program.push_instruction(ins);
             }
@@ -345,27 +370,25 @@ pub fn assemble_lines(lines: Vec<Line>) -> Result<Program, AssemblerError> {
     }
 
     for line in lines {
-        match line {
-            Line::Constant { constant, .. } => match constant {
-                Constant::String(string) => {
-                    program.push_word(Word::from_u32(string.len() as u32));
-
-                    let mut bytes = string.bytes();
+        if let Line::Constant {
+            constant: Constant::String(string),
+            ..
+        } = line
+        {
+            program.push_word(Word::from_u32(string.len() as u32));
+
+            let mut bytes = string.bytes();
+
+            for _ in 0..align(string.len() as u32, 4) / 4 {
+                let data = Word::from_bytes([
+                    bytes.next().unwrap_or(0),
+                    bytes.next().unwrap_or(0),
+                    bytes.next().unwrap_or(0),
+                    bytes.next().unwrap_or(0),
+                ]);
 
-                    for _ in 0..align(string.len() as u32, 4) / 4 {
-                        let data = Word::from_bytes([
-                            bytes.next().unwrap_or(0),
-                            bytes.next().unwrap_or(0),
-                            bytes.next().unwrap_or(0),
-                            bytes.next().unwrap_or(0),
-                        ]);
-
-                        program.push_word(data);
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
+                program.push_word(data);
+            }
         }
     }
 
@@ -0,0 +1,401 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+use proxy::*;
+
+use crate::{
+    load,
+    run::{output_uart, setup, Machine, Options, State},
+    Error,
+};
+
+/// Number of instructions that can be stepped back.
+const HISTORY: usize = 1 << 16;
+
+/// Bytes reserved for the instructions typed into the repl.
+const REPL_CODE: u32 = 4096;
+
+const DEBUG_HELP: &str = "\
+commands:
+    s, step [n]                  execute n instructions, 1 by default
+    c, continue                  run until a breakpoint, watchpoint or exit
+    back [n]                     undo n instructions, 1 by default
+    b, break <address>           stop before executing the instruction at address
+    delete <address>             remove a breakpoint
+    w, watch <address> [len] [r|w|rw]
+                                 stop after accesses of len bytes at address, writes by default
+    r, regs                      print the registers
+    m, mem <address> [len]       print len bytes of memory, 64 by default
+    q, quit                      stop debugging
+addresses are absolute, programs are loaded at system memory.";
+
+const REPL_HELP: &str = "\
+instructions are executed as they are typed, jumps leaving the line return to the prompt.
+commands:
+    :regs                        print the registers
+    :mem <address> [len]         print len bytes of memory, 64 by default
+    :quit                        leave the repl";
+
+/// Parses a decimal or `0x` hexadecimal number.
+fn parse_number(src: &str) -> Result<u32, String> {
+    let number = match src.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => src.parse(),
+    };
+
+    number.map_err(|_| format!("invalid number `{}`", src))
+}
+
+/// Parses the address that is the first argument of a command.
+fn address(args: &[&str]) -> Result<u32, String> {
+    parse_number(args.first().ok_or("expected an address")?)
+}
+
+fn number_or(src: Option<&str>, default: u32) -> Result<u32, String> {
+    src.map_or(Ok(default), parse_number)
+}
+
+/// Reads a line from stdin after printing `prompt`, `None` at the end of input.
+fn prompt(prompt: &str) -> io::Result<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(line.trim().to_owned()))
+}
+
+fn print_registers(cpu: &Cpu<State>) {
+    for (index, word) in cpu.registers().words().iter().enumerate() {
+        let register = Register::new(index as u8);
+        println!(
+            "{:>4}  {:#010x}  {}",
+            register,
+            word.to_u32(),
+            word.to_i32()
+        );
+    }
+}
+
+fn print_memory(cpu: &Cpu<State>, args: &[&str]) -> Result<(), String> {
+    let address = address(args)?;
+    let len = number_or(args.get(1).copied(), 64)?;
+
+    let bytes = cpu
+        .memory()
+        .read_bytes(address, len)
+        .map_err(|_| format!("{} bytes at {:#x} are out of bounds", len, address))?;
+
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let text = chunk
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7e => byte as char,
+                _ => '.',
+            })
+            .collect::<String>();
+
+        println!("{:#010x}  {:<47}  {}", address + row as u32 * 16, hex, text);
+    }
+
+    Ok(())
+}
+
+/// Prints the instruction at `eip`.
+fn print_location(cpu: &Cpu<State>) {
+    let eip = cpu.registers().eip().to_u32();
+
+    // constants take two words
+    let memory = cpu.memory();
+    let bytes = memory
+        .read_bytes(eip, Word::SIZE * 2)
+        .or_else(|_| memory.read_bytes(eip, Word::SIZE));
+
+    match bytes {
+        Ok(bytes) => {
+            let program = Program::from_bytes(bytes.into_owned());
+            let (_, decoded) = disassemble(&program)[0];
+
+            println!("{:#010x}  {}", eip, decoded);
+        }
+        Err(_) => println!("{:#010x}  out of bounds", eip),
+    }
+}
+
+fn watch(cpu: &mut Cpu<State>, args: &[&str]) -> Result<(), String> {
+    let address = address(args)?;
+    let len = number_or(args.get(1).copied(), Word::SIZE)?;
+
+    let kind = match args.get(2).copied() {
+        Some("r") => WatchKind::Read,
+        None | Some("w") => WatchKind::Write,
+        Some("rw") => WatchKind::ReadWrite,
+        Some(kind) => return Err(format!("invalid kind `{}`, expected r, w or rw", kind)),
+    };
+
+    let id = cpu.add_watchpoint(address, len, kind);
+    println!("watchpoint {} at {:#x}", id, address);
+
+    Ok(())
+}
+
+/// Heaps before the instructions that moved the break, with the cycle count
+/// of the instruction.
+///
+/// The heap is host state that [`Cpu::step_back`] doesn't undo.
+type HeapHistory = VecDeque<(u64, Heap)>;
+
+/// Executes an instruction, returns true if the cpu stopped.
+fn step(cpu: &mut Cpu<State>, state: &mut State, heaps: &mut HeapHistory) -> bool {
+    let cycles = cpu.cycles();
+    let heap = state.heap;
+
+    let result = cpu.eval_instruction(state);
+
+    if state.heap != heap {
+        if heaps.len() == HISTORY {
+            heaps.pop_front();
+        }
+
+        heaps.push_back((cycles, heap));
+    }
+
+    match result {
+        Ok(None) => false,
+        Ok(Some(Stop::Exit(code))) => {
+            println!("exited with ({})", code);
+            true
+        }
+        Ok(Some(Stop::Watchpoint(hit))) => {
+            println!("{}", hit);
+            true
+        }
+        Err(fault) => {
+            println!("fault: {}", fault);
+            true
+        }
+    }
+}
+
+/// Runs a program under an interactive debugger reading commands from stdin.
+pub fn debug(options: Options) -> Result<ExitCode, Error> {
+    let path = options.program()?.to_owned();
+    let program = load(&path)?;
+
+    let Machine {
+        mut cpu, mut state, ..
+    } = setup(options, &program, output_uart())?;
+
+    cpu.set_history_limit(HISTORY);
+
+    let mut breakpoints = Vec::new();
+    let mut heaps = HeapHistory::new();
+
+    println!("debugging {}, type `help` for commands", path);
+    print_location(&cpu);
+
+    while let Some(line) = prompt("(proxy) ")? {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let Some((&command, args)) = words.split_first() else {
+            continue;
+        };
+
+        let result = match command {
+            "s" | "step" => number_or(args.first().copied(), 1).map(|count| {
+                for _ in 0..count {
+                    if step(&mut cpu, &mut state, &mut heaps) {
+                        break;
+                    }
+                }
+
+                print_location(&cpu);
+            }),
+            "c" | "continue" => {
+                while !step(&mut cpu, &mut state, &mut heaps) {
+                    let eip = cpu.registers().eip().to_u32();
+
+                    if breakpoints.contains(&eip) {
+                        println!("breakpoint at {:#x}", eip);
+                        break;
+                    }
+                }
+
+                print_location(&cpu);
+                Ok(())
+            }
+            "back" => number_or(args.first().copied(), 1).map(|count| {
+                for _ in 0..count {
+                    if !cpu.step_back() {
+                        println!("no more instructions to undo");
+                        break;
+                    }
+
+                    // the undone instruction moved the break
+                    let cycles = cpu.cycles();
+
+                    if let Some(&(_, heap)) = heaps.back().filter(|&&(at, _)| at >= cycles) {
+                        state.heap = heap;
+                        heaps.pop_back();
+                    }
+                }
+
+                print_location(&cpu);
+            }),
+            "b" | "break" => address(args).map(|address| {
+                breakpoints.push(address);
+                println!("breakpoint at {:#x}", address);
+            }),
+            "delete" => address(args).map(|address| {
+                breakpoints.retain(|&other| other != address);
+            }),
+            "w" | "watch" => watch(&mut cpu, args),
+            "r" | "regs" => {
+                print_registers(&cpu);
+                Ok(())
+            }
+            "m" | "mem" => print_memory(&cpu, args),
+            "h" | "help" => {
+                println!("{}", DEBUG_HELP);
+                Ok(())
+            }
+            "q" | "quit" => break,
+            _ => Err(format!(
+                "unknown command `{}`, type `help` for commands",
+                command
+            )),
+        };
+
+        if let Err(message) = result {
+            println!("error: {}", message);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Assembles `line` at `code` and executes it, printing the registers it
+/// changed. Returns the exit code if the program exited.
+fn execute(
+    cpu: &mut Cpu<State>,
+    state: &mut State,
+    line: &str,
+    code: u32,
+) -> Result<Option<u32>, String> {
+    let lines = parse_file(line).map_err(|error| error.message().to_owned())?;
+
+    // string constants are placed after the instructions, execution ends before them
+    let code_len = lines
+        .iter()
+        .map(|line| match line {
+            Line::Constant { .. } | Line::Compare { .. } => 2 * Word::SIZE,
+            Line::Instruction(_) => Word::SIZE,
+            _ => 0,
+        })
+        .sum::<u32>();
+
+    let program = assemble_lines(lines).map_err(|error| error.message().to_owned())?;
+
+    if program.len() > REPL_CODE {
+        return Err(format!("instructions are larger than {} bytes", REPL_CODE));
+    }
+
+    cpu.memory_mut()
+        .write_bytes(code, program.bytes())
+        .map_err(|error| error.to_string())?;
+    cpu.registers_mut().write_eip(Word::from_u32(code));
+
+    let before = cpu.registers().clone();
+
+    // run until the instructions are done or jump away
+    while (code..code + code_len).contains(&cpu.registers().eip().to_u32()) {
+        match cpu.eval_instruction(state) {
+            Ok(None) => {}
+            Ok(Some(Stop::Exit(exit_code))) => {
+                println!("exited with ({})", exit_code);
+                return Ok(Some(exit_code));
+            }
+            Ok(Some(Stop::Watchpoint(hit))) => return Err(hit.to_string()),
+            Err(fault) => return Err(format!("fault: {}", fault)),
+        }
+    }
+
+    let words = before.words().iter().zip(cpu.registers().words());
+
+    for (index, (before, after)) in words.enumerate() {
+        let register = Register::new(index as u8);
+
+        if before != after && register != Register::EIP {
+            println!(
+                "{} = {} ({:#010x})",
+                register,
+                after.to_i32(),
+                after.to_u32()
+            );
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads instructions from stdin and executes them as they are typed.
+pub fn repl(options: Options) -> Result<ExitCode, Error> {
+    if let Some(program) = options.program {
+        return Err(crate::usage(format!("unexpected argument `{}`", program)));
+    }
+
+    // instructions are written over the start of the program and executed
+    let program = Program::from_bytes(vec![0; REPL_CODE as usize]);
+
+    let Machine {
+        mut cpu, mut state, ..
+    } = setup(options, &program, output_uart())?;
+
+    let code = cpu.abi().system_memory;
+
+    println!("type instructions to execute them, `:help` for commands");
+
+    while let Some(line) = prompt("> ")? {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        let result = match words.split_first() {
+            None => Ok(()),
+            Some((&":regs", _)) => {
+                print_registers(&cpu);
+                Ok(())
+            }
+            Some((&":mem", args)) => print_memory(&cpu, args),
+            Some((&":help", _)) => {
+                println!("{}", REPL_HELP);
+                Ok(())
+            }
+            Some((&":quit", _)) => break,
+            Some((command, _)) if command.starts_with(':') => Err(format!(
+                "unknown command `{}`, type `:help` for commands",
+                command
+            )),
+            Some(_) => match execute(&mut cpu, &mut state, &line, code) {
+                Ok(Some(exit_code)) => return Ok(ExitCode::from(exit_code as u8)),
+                Ok(None) => Ok(()),
+                Err(message) => Err(message),
+            },
+        };
+
+        if let Err(message) = result {
+            println!("error: {}", message);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
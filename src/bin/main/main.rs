@@ -0,0 +1,249 @@
+mod debug;
+mod run;
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+use proxy::*;
+
+const USAGE: &str = "\
+usage: proxy <command> [arguments]
+
+commands:
+    run <program> [options] [-- args...]    run a program, the default command
+    asm <source> [-o <output>]              assemble a source file to a program file
+    check <source>...                       report errors in source files without running them
+    disasm <program>                        print the instructions of a program
+    debug <program> [options] [-- args...]  step through a program interactively
+    repl [options]                          execute instructions as they are typed
+    help                                    print this message
+
+programs are assembly sources or program files written by `asm`.
+
+options:
+    --env <key>=<value>           add an environment string
+    --root <dir>                  directory the guest sees as `/` read only, the current directory by default
+    --mount <guest>=<host>        mount a host directory at a guest path
+    --mount-ro <guest>=<host>     mount a host directory read only
+    --disk <image>                map a disk image as a block device
+    --framebuffer <width>x<height> map a framebuffer
+    --frame <path>                save the framebuffer when the program exits, as png or ppm
+    --frame-every <n>             also save numbered frames every n instructions
+    --config <path>               read the abi and sys call table from a config file
+    --registers <n>               number of registers
+    --system-memory <n>           size of system memory, where programs are loaded
+    --memory <n>                  size of memory, up to 4G
+    --device-memory <address>     start of the device range, devices hide the memory behind them
+    --stack <address>             address of the stack at entry
+    --stack-size <n>              bytes between the stack and the heap
+    --entry <offset>              offset of the first instruction in the program
+    --sys-call <name>=<address>   move a sys call
+
+`run` exits with the exit code of the program, other commands exit with 0.
+errors exit with 1 and invalid arguments with 2.
+";
+
+/// Why a command failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments are invalid, exits with 2.
+    Usage(String),
+    /// Exits with 1.
+    Failed(String),
+}
+
+pub fn usage(message: impl Into<String>) -> Error {
+    Error::Usage(message.into())
+}
+
+impl<E: std::error::Error> From<E> for Error {
+    fn from(error: E) -> Self {
+        Self::Failed(error.to_string())
+    }
+}
+
+/// Formats an assembler error with the line of `source` it is on.
+fn diagnostic(path: &str, source: &str, error: &AssemblerError) -> String {
+    let Some(line) = error.line() else {
+        return format!("{}: {}", path, error.message());
+    };
+
+    let text = source.lines().nth(line - 1).unwrap_or_default().trim_end();
+    let gutter = " ".repeat(line.to_string().len());
+
+    format!(
+        "{}\n{}--> {}:{}\n{} |\n{} | {}\n{} |",
+        error.message(),
+        gutter,
+        path,
+        line,
+        gutter,
+        line,
+        text,
+        gutter
+    )
+}
+
+fn read_source(path: &str) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|error| Error::Failed(format!("{}: {}", path, error)))
+}
+
+fn assemble_source(path: &str, source: &str) -> Result<Program, Error> {
+    assemble(source).map_err(|error| Error::Failed(diagnostic(path, source, &error)))
+}
+
+/// Reads a program file, or assembles a source file.
+pub fn load(path: &str) -> Result<Program, Error> {
+    let bytes = fs::read(path).map_err(|error| Error::Failed(format!("{}: {}", path, error)))?;
+
+    if bytes.starts_with(Program::MAGIC) {
+        return Program::read_from(&mut bytes.as_slice())
+            .map_err(|error| Error::Failed(format!("{}: {}", path, error)));
+    }
+
+    let source = String::from_utf8(bytes)
+        .map_err(|_| Error::Failed(format!("{}: not a program or a source file", path)))?;
+
+    assemble_source(path, &source)
+}
+
+fn asm(args: Vec<String>) -> Result<ExitCode, Error> {
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(
+                    args.next()
+                        .ok_or_else(|| usage("expected a path after -o"))?,
+                )
+            }
+            _ if arg.starts_with('-') => return Err(usage(format!("unknown option `{}`", arg))),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(usage(format!("unexpected argument `{}`", arg))),
+        }
+    }
+
+    let input = input.ok_or_else(|| usage("expected a source file"))?;
+    let output = output.unwrap_or_else(|| {
+        let path = Path::new(&input).with_extension("bin");
+        path.to_string_lossy().into_owned()
+    });
+
+    if Path::new(&output) == Path::new(&input) {
+        return Err(usage(format!(
+            "`{}` would overwrite the source, choose the output with -o",
+            input
+        )));
+    }
+
+    let program = assemble_source(&input, &read_source(&input)?)?;
+
+    program
+        .save(&output)
+        .map_err(|error| Error::Failed(format!("{}: {}", output, error)))?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check(args: Vec<String>) -> Result<ExitCode, Error> {
+    if args.is_empty() {
+        return Err(usage("expected a source file"));
+    }
+
+    let mut failed = false;
+
+    // report every file before failing
+    for path in args {
+        let result = read_source(&path).and_then(|source| assemble_source(&path, &source));
+
+        if let Err(Error::Failed(message) | Error::Usage(message)) = result {
+            eprintln!("error: {}", message);
+            failed = true;
+        }
+    }
+
+    match failed {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+fn disasm(args: Vec<String>) -> Result<ExitCode, Error> {
+    let [path] = args.as_slice() else {
+        return Err(usage("expected a program"));
+    };
+
+    let program = load(path)?;
+
+    let mut stdout = io::stdout().lock();
+
+    for (offset, decoded) in disassemble(&program) {
+        match writeln!(stdout, "{:#06x}  {}", offset, decoded) {
+            Ok(()) => {}
+            // the reader has all it wants, like `head`
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+
+    let command = match args.first().map(String::as_str) {
+        None => {
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+        Some("help" | "-h" | "--help") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(command @ ("run" | "asm" | "check" | "disasm" | "debug" | "repl")) => {
+            let command = command.to_owned();
+            args.remove(0);
+            command
+        }
+        // `run` is the default command
+        Some(_) => String::from("run"),
+    };
+
+    // arguments after `--` belong to the guest
+    let mut options = args.iter().take_while(|arg| *arg != "--");
+
+    if options.any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let result = match command.as_str() {
+        "asm" => asm(args),
+        "check" => check(args),
+        "disasm" => disasm(args),
+        "debug" => run::Options::parse(args).and_then(debug::debug),
+        "repl" => run::Options::parse(args).and_then(debug::repl),
+        _ => run::Options::parse(args).and_then(run::run),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(Error::Usage(message)) => {
+            eprintln!("error: {}\n\nrun `proxy help` for usage", message);
+            ExitCode::from(2)
+        }
+        Err(Error::Failed(message)) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
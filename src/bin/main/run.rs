@@ -0,0 +1,386 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc,
+};
+
+use proxy::*;
+
+use crate::{load, usage, Error};
+
+/// Sys calls by name, with their default address and how many addresses they use.
+const SYS_CALLS: [(&str, u32, u32); 4] = [
+    ("print", 0, 1),
+    ("asm", 2, 1),
+    ("files", 8, Files::STAT + 1),
+    ("heap", 16, Heap::SBRK + 1),
+];
+
+const ASM_OFFSET: u32 = 128;
+
+const TIMER_LINE: u8 = 0;
+
+const UART_OFFSET: u32 = 0x1000;
+const DISK_OFFSET: u32 = 0x2000;
+const FRAMEBUFFER_OFFSET: u32 = 0x0010_0000;
+
+#[derive(Default)]
+pub struct State {
+    files: Files,
+    pub heap: Heap,
+}
+
+impl AsMut<Files> for State {
+    fn as_mut(&mut self) -> &mut Files {
+        &mut self.files
+    }
+}
+
+impl AsMut<Heap> for State {
+    fn as_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+}
+
+/// Options of the commands that run programs.
+#[derive(Default)]
+pub struct Options {
+    pub program: Option<String>,
+    disk: Option<String>,
+    framebuffer: Option<Framebuffer>,
+    frame_path: Option<String>,
+    frame_every: u64,
+    root: Option<String>,
+    mounts: Vec<(String, Mount)>,
+    guest_args: Vec<String>,
+    guest_env: Vec<String>,
+    config_path: Option<String>,
+    settings: Vec<(&'static str, String, String)>,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, Error> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |what: &str| {
+                args.next()
+                    .ok_or_else(|| usage(format!("expected {} after {}", what, arg)))
+            };
+
+            match arg.as_str() {
+                "--" => options.guest_args.extend(args.by_ref()),
+                "--env" => options.guest_env.push(value("KEY=VALUE")?),
+                "--disk" => options.disk = Some(value("a path")?),
+                "--framebuffer" => {
+                    let size = value("a size")?;
+
+                    let (width, height) = size
+                        .split_once('x')
+                        .and_then(|(width, height)| {
+                            Some((width.parse().ok()?, height.parse().ok()?))
+                        })
+                        .ok_or_else(|| usage(format!("expected WIDTHxHEIGHT, found `{}`", size)))?;
+
+                    let framebuffer = Framebuffer::try_new(width, height)
+                        .map_err(|error| usage(error.to_string()))?;
+
+                    options.framebuffer = Some(framebuffer);
+                }
+                "--frame" => options.frame_path = Some(value("a path")?),
+                "--frame-every" => {
+                    let count = value("a count")?;
+
+                    options.frame_every = count
+                        .parse()
+                        .map_err(|_| usage(format!("invalid count `{}`", count)))?;
+                }
+                "--root" => options.root = Some(value("a path")?),
+                "--mount" | "--mount-ro" => {
+                    let mount = value("GUEST=HOST")?;
+                    let (guest, host) = mount
+                        .split_once('=')
+                        .ok_or_else(|| usage(format!("expected GUEST=HOST, found `{}`", mount)))?;
+
+                    let mount = match arg.as_str() {
+                        "--mount" => Mount::host(host),
+                        _ => Mount::host_read_only(host),
+                    };
+
+                    options.mounts.push((guest.to_owned(), mount));
+                }
+                "--config" => options.config_path = Some(value("a path")?),
+                "--registers" | "--system-memory" | "--memory" | "--device-memory" | "--stack"
+                | "--stack-size" | "--entry" => {
+                    let key = match arg.as_str() {
+                        "--registers" => "register_count",
+                        "--system-memory" => "system_memory",
+                        "--memory" => "memory_size",
+                        "--device-memory" => "device_memory",
+                        "--stack" => "stack_address",
+                        "--stack-size" => "stack_size",
+                        _ => "entry",
+                    };
+
+                    let number = value("a number")?;
+                    options.settings.push(("abi", key.to_owned(), number));
+                }
+                "--sys-call" => {
+                    let sys_call = value("NAME=ADDRESS")?;
+                    let (name, address) = sys_call.split_once('=').ok_or_else(|| {
+                        usage(format!("expected NAME=ADDRESS, found `{}`", sys_call))
+                    })?;
+
+                    options
+                        .settings
+                        .push(("sys_calls", name.to_owned(), address.to_owned()));
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(usage(format!("unknown option `{}`", arg)));
+                }
+                _ if options.program.is_none() => options.program = Some(arg),
+                _ => return Err(usage(format!("unexpected argument `{}`", arg))),
+            }
+        }
+
+        if options.framebuffer.is_none() && options.frame_path.is_some() {
+            return Err(usage("--frame requires --framebuffer"));
+        }
+
+        if options.frame_path.is_none() && options.frame_every > 0 {
+            return Err(usage("--frame-every requires --frame"));
+        }
+
+        Ok(options)
+    }
+
+    /// Returns the path of the program, which is required by every command
+    /// but `repl`.
+    pub fn program(&self) -> Result<&str, Error> {
+        self.program
+            .as_deref()
+            .ok_or_else(|| usage("expected a program"))
+    }
+}
+
+/// A cpu with its devices and sys calls, ready to run a program.
+pub struct Machine {
+    pub cpu: Cpu<State>,
+    pub state: State,
+    pub framebuffer: Option<Framebuffer>,
+}
+
+/// Reads the string a sys call is passed, failing like file sys calls if the
+/// guest can't read it.
+fn guest_string(cpu: &CpuState, ptr: u32, len: u32) -> Result<String, FileError> {
+    if !cpu.is_allowed(ptr, len, Access::Read) {
+        return Err(FileError::BadAddress);
+    }
+
+    match cpu.memory.read_string(ptr, len) {
+        Ok(string) => Ok(string.into_owned()),
+        Err(_) => Err(FileError::BadAddress),
+    }
+}
+
+/// Writes `-1` to `eax` and `error` to `ebx`, like file sys calls do.
+fn fail(cpu: &mut CpuState, error: FileError) {
+    cpu.registers.write(Register::EAX, Word::from_u32(u32::MAX));
+    cpu.registers
+        .write(Register::EBX, Word::from_u32(error as u32));
+}
+
+/// Returns a uart writing to stdout without input, for commands that read
+/// commands from stdin.
+pub fn output_uart() -> Uart {
+    let (_, receiver) = mpsc::channel();
+    Uart::new(receiver, io::stdout())
+}
+
+/// Creates the machine described by `options` and loads `program` into it.
+pub fn setup(options: Options, program: &Program, uart: Uart) -> Result<Machine, Error> {
+    let mut config = match options.config_path {
+        Some(config_path) => {
+            let source = fs::read_to_string(&config_path)
+                .map_err(|error| Error::Failed(format!("{}: {}", config_path, error)))?;
+
+            Config::parse(&source)
+                .map_err(|error| Error::Failed(format!("{}: {}", config_path, error)))?
+        }
+        None => Config::default(),
+    };
+
+    // flags override the config file
+    for (section, key, value) in options.settings {
+        config
+            .set(section, &key, &value)
+            .map_err(|error| usage(error.to_string()))?;
+    }
+
+    let sys_calls = config
+        .sys_call_addresses(&SYS_CALLS)
+        .map_err(|error| Error::Failed(error.to_string()))?;
+
+    let mut cpu = Cpu::<State>::try_new(config.abi)
+        .map_err(|error| Error::Failed(format!("invalid abi: {}", error)))?;
+
+    cpu.register_sys_call(sys_calls["print"], |cpu, _| {
+        let ptr = cpu.registers.read(Register::EAX).to_u32();
+        let len = cpu.registers.read(Register::EBX).to_u32();
+
+        match guest_string(cpu, ptr, len) {
+            Ok(string) => println!("{}", string),
+            Err(error) => fail(cpu, error),
+        }
+    });
+    cpu.register_sys_call(sys_calls["asm"], |cpu, _| {
+        let source_ptr = cpu.registers.read(Register::EAX).to_u32();
+        let source_len = cpu.registers.read(Register::EBX).to_u32();
+
+        let program = guest_string(cpu, source_ptr, source_len)
+            .and_then(|source| assemble(&source).map_err(|_| FileError::InvalidArgument))
+            // the program goes in system memory
+            .and_then(|program| {
+                let end = ASM_OFFSET as u64 + program.len() as u64;
+
+                (end <= cpu.abi().system_memory as u64)
+                    .then_some(program)
+                    .ok_or(FileError::InvalidArgument)
+            });
+
+        let program = match program {
+            Ok(program) => program,
+            Err(error) => return fail(cpu, error),
+        };
+
+        // in system memory, so writing can't fail
+        let _ = cpu.memory.write_bytes(ASM_OFFSET, program.bytes());
+
+        cpu.registers
+            .write(Register::EAX, Word::from_u32(ASM_OFFSET));
+        cpu.registers
+            .write(Register::EBX, Word::from_u32(program.len()));
+    });
+    Files::register(&mut cpu, sys_calls["files"])?;
+    Heap::register(&mut cpu, sys_calls["heap"])?;
+
+    let timer_address = cpu.abi().device_memory;
+    cpu.map_device(timer_address, Timer::SIZE, Timer::new(TIMER_LINE));
+
+    let uart_address = cpu.abi().device_memory + UART_OFFSET;
+    cpu.map_device(uart_address, Uart::SIZE, uart);
+
+    if let Some(disk) = options.disk {
+        let disk_address = cpu.abi().device_memory + DISK_OFFSET;
+        let device = BlockDevice::open(&disk)
+            .map_err(|error| Error::Failed(format!("{}: {}", disk, error)))?;
+
+        cpu.map_device(disk_address, BlockDevice::SIZE, device);
+    }
+
+    if let Some(ref framebuffer) = options.framebuffer {
+        let framebuffer_address = cpu.abi().device_memory as u64 + FRAMEBUFFER_OFFSET as u64;
+
+        if framebuffer_address + framebuffer.size() as u64 > 1 << 32 {
+            return Err(Error::Failed(format!(
+                "framebuffer at {:#x} doesn't fit below 4 GiB",
+                framebuffer_address
+            )));
+        }
+
+        let framebuffer_address = framebuffer_address as u32;
+        cpu.map_device(framebuffer_address, framebuffer.size(), framebuffer.clone());
+    }
+
+    // like a host process, the program path is the first argument
+    let mut guest_args = options.guest_args;
+    if let Some(path) = options.program {
+        guest_args.insert(0, path);
+    }
+
+    cpu.load_program_with_args(program, &guest_args, &options.guest_env)?;
+
+    // guests only see the root directory and the mounts, writing needs a mount
+    let mut vfs = Vfs::new();
+    let root = options.root.unwrap_or_else(|| ".".into());
+    vfs.mount("/", Mount::host_read_only(root));
+    for (guest, mount) in options.mounts {
+        vfs.mount(&guest, mount);
+    }
+
+    let state = State {
+        files: Files::new(vfs),
+        heap: Heap::after_stack(&cpu),
+    };
+
+    Ok(Machine {
+        cpu,
+        state,
+        framebuffer: options.framebuffer,
+    })
+}
+
+/// Returns `path` with `index` appended to the file stem, `frame.png` becomes `frame-0001.png`.
+fn numbered(path: &Path, index: u64) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut name = format!("{}-{:04}", stem, index);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+
+    path.with_file_name(name)
+}
+
+/// Runs a program, exiting with its exit code.
+pub fn run(options: Options) -> Result<ExitCode, Error> {
+    let path = options.program()?.to_owned();
+    let program = load(&path)?;
+
+    let frame_path = options.frame_path.clone();
+    let frame_every = options.frame_every;
+
+    let Machine {
+        mut cpu,
+        mut state,
+        framebuffer,
+    } = setup(options, &program, Uart::stdio())?;
+
+    let stop = match (&framebuffer, &frame_path) {
+        (Some(framebuffer), Some(frame_path)) if frame_every > 0 => {
+            let frame_path = Path::new(frame_path);
+            let mut instructions = 0;
+
+            loop {
+                if let Some(stop) = cpu.eval_instruction(&mut state)? {
+                    break stop;
+                }
+
+                instructions += 1;
+                if instructions % frame_every == 0 {
+                    let path = numbered(frame_path, instructions / frame_every);
+                    framebuffer.frame().save(path)?;
+                }
+            }
+        }
+        _ => cpu.run(&mut state)?,
+    };
+
+    if let (Some(framebuffer), Some(frame_path)) = (framebuffer, frame_path) {
+        framebuffer.frame().save(frame_path)?;
+    }
+
+    match stop {
+        Stop::Exit(exit_code) => {
+            // stdout is the guest's
+            eprintln!("exited with ({})", exit_code);
+
+            // like host processes only the low byte is kept
+            Ok(ExitCode::from(exit_code as u8))
+        }
+        Stop::Watchpoint(hit) => Err(Error::Failed(format!("stopped at {}", hit))),
+    }
+}
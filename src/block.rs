@@ -0,0 +1,237 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{Access, Device, Memory, Word};
+
+trait Storage: Read + Write + Seek {}
+
+impl<S: Read + Write + Seek> Storage for S {}
+
+/// A block device of fixed size sectors.
+///
+/// Registers are words at these offsets:
+///
+///  Offset | Register
+/// --------|----------
+///  `0`    | command, writing one of the commands runs it
+///  `4`    | sector
+///  `8`    | physical memory address for dma commands
+///  `12`   | number of sectors for dma commands
+///  `16`   | status, [`BlockDevice::OK`] or [`BlockDevice::ERROR`] after a command
+///  `20`   | number of sectors, read only
+///
+/// The [`BlockDevice::SECTOR_SIZE`] bytes from [`BlockDevice::BUFFER`] hold the
+/// sector buffer.
+pub struct BlockDevice {
+    storage: Box<dyn Storage>,
+    sectors: u32,
+    sector: u32,
+    address: u32,
+    count: u32,
+    status: u32,
+    /// Dma command waiting for access to memory.
+    command: Option<u32>,
+    buffer: Box<[u8; Self::SECTOR_SIZE as usize]>,
+}
+
+impl BlockDevice {
+    pub const SECTOR_SIZE: u32 = 512;
+    pub const SIZE: u32 = Self::BUFFER + Self::SECTOR_SIZE;
+
+    pub const COMMAND: u32 = 0;
+    pub const SECTOR: u32 = 4;
+    pub const ADDRESS: u32 = 8;
+    pub const COUNT: u32 = 12;
+    pub const STATUS: u32 = 16;
+    pub const SECTORS: u32 = 20;
+    pub const BUFFER: u32 = 512;
+
+    /// Reads the sector into the buffer.
+    pub const READ: u32 = 1;
+    /// Writes the buffer to the sector.
+    pub const WRITE: u32 = 2;
+    /// Reads `count` sectors starting at the sector into memory at the address.
+    pub const DMA_READ: u32 = 3;
+    /// Writes `count` sectors from memory at the address starting at the sector.
+    pub const DMA_WRITE: u32 = 4;
+
+    pub const OK: u32 = 0;
+    pub const ERROR: u32 = 1;
+
+    /// Creates a block device backed by `storage`, a trailing partial sector
+    /// is not accessible.
+    pub fn new(mut storage: impl Read + Write + Seek + 'static) -> io::Result<Self> {
+        let len = storage.seek(SeekFrom::End(0))?;
+        let sectors = (len / Self::SECTOR_SIZE as u64).min(u32::MAX as u64) as u32;
+
+        Ok(Self {
+            storage: Box::new(storage),
+            sectors,
+            sector: 0,
+            address: 0,
+            count: 1,
+            status: Self::OK,
+            command: None,
+            buffer: Box::new([0; Self::SECTOR_SIZE as usize]),
+        })
+    }
+
+    /// Opens the image file at `path` for reading and writing.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::new(file)
+    }
+
+    /// Creates a block device backed by `bytes` in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::new(Cursor::new(bytes)).expect("seeking a cursor can't fail")
+    }
+
+    pub fn sectors(&self) -> u32 {
+        self.sectors
+    }
+
+    fn seek(&mut self, sector: u32, count: u32) -> io::Result<()> {
+        if sector as u64 + count as u64 > self.sectors as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sector out of range",
+            ));
+        }
+
+        let offset = sector as u64 * Self::SECTOR_SIZE as u64;
+        self.storage.seek(SeekFrom::Start(offset))?;
+
+        Ok(())
+    }
+
+    fn run(&mut self, command: u32) -> io::Result<()> {
+        match command {
+            Self::READ => {
+                self.seek(self.sector, 1)?;
+                self.storage.read_exact(&mut self.buffer[..])
+            }
+            Self::WRITE => {
+                self.seek(self.sector, 1)?;
+                self.storage.write_all(&self.buffer[..])?;
+                self.storage.flush()
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid command",
+            )),
+        }
+    }
+
+    fn run_dma(&mut self, command: u32, memory: &mut Memory) -> io::Result<()> {
+        let len = self.count as u64 * Self::SECTOR_SIZE as u64;
+
+        if len > u32::MAX as u64 || !memory.in_bounds(self.address, len as u32) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "address out of bounds",
+            ));
+        }
+
+        // dma is bound by the permissions of memory like the cpu
+        let access = match command {
+            Self::DMA_READ => Access::Write,
+            _ => Access::Read,
+        };
+
+        if !memory.is_allowed(self.address, len as u32, access) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "memory not accessible",
+            ));
+        }
+
+        self.seek(self.sector, self.count)?;
+
+        if command == Self::DMA_READ {
+            let mut bytes = vec![0; len as usize];
+            self.storage.read_exact(&mut bytes)?;
+
+            memory
+                .write_bytes(self.address, &bytes)
+                .map_err(io::Error::other)
+        } else {
+            let bytes = memory
+                .read_bytes(self.address, len as u32)
+                .map_err(io::Error::other)?;
+
+            self.storage.write_all(&bytes)?;
+            self.storage.flush()
+        }
+    }
+
+    fn status_of(result: io::Result<()>) -> u32 {
+        match result {
+            Ok(()) => Self::OK,
+            Err(_) => Self::ERROR,
+        }
+    }
+}
+
+impl Device for BlockDevice {
+    fn read(&mut self, offset: u32, width: u8) -> Word {
+        if offset >= Self::BUFFER {
+            let start = (offset - Self::BUFFER) as usize;
+            let end = (start + width.min(Word::WIDTH) as usize).min(self.buffer.len());
+
+            let mut bytes = [0; 4];
+            bytes[4 - (end - start)..].copy_from_slice(&self.buffer[start..end]);
+
+            return Word::from_bytes(bytes);
+        }
+
+        let value = match offset {
+            Self::SECTOR => self.sector,
+            Self::ADDRESS => self.address,
+            Self::COUNT => self.count,
+            Self::STATUS => self.status,
+            Self::SECTORS => self.sectors,
+            _ => 0,
+        };
+
+        Word::from_u32(value)
+    }
+
+    fn write(&mut self, offset: u32, data: Word, width: u8) {
+        if offset >= Self::BUFFER {
+            let start = (offset - Self::BUFFER) as usize;
+            let end = (start + width.min(Word::WIDTH) as usize).min(self.buffer.len());
+
+            let bytes = data.to_bytes();
+            self.buffer[start..end].copy_from_slice(&bytes[4 - (end - start)..]);
+
+            return;
+        }
+
+        let value = data.to_u32();
+
+        match offset {
+            Self::COMMAND if matches!(value, Self::DMA_READ | Self::DMA_WRITE) => {
+                self.command = Some(value);
+            }
+            Self::COMMAND => self.status = Self::status_of(self.run(value)),
+            Self::SECTOR => self.sector = value,
+            Self::ADDRESS => self.address = value,
+            Self::COUNT => self.count = value,
+            _ => {}
+        }
+    }
+
+    fn dma(&mut self, memory: &mut Memory) -> bool {
+        match self.command.take() {
+            Some(command) => {
+                self.status = Self::status_of(self.run_dma(command, memory));
+                command == Self::DMA_READ && self.status == Self::OK
+            }
+            None => false,
+        }
+    }
+}
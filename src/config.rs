@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use crate::{Abi, Permissions};
+
+/// An [`Abi`] and sys call table, read from INI style files:
+///
+/// ```text
+/// # comments start with `#` or `;`
+/// [abi]
+/// register_count = 32
+/// memory_size = 1M
+/// system_permissions = rx
+///
+/// [sys_calls]
+/// print = 0x20
+/// ```
+///
+/// Numbers are decimal, or hexadecimal with `0x`, optionally followed by `K`,
+/// `M` or `G` for multiples of 1024. The config isn't validated, use
+/// [`Abi::validate`] once all settings are applied.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    pub abi: Abi,
+    /// Addresses of sys calls by name, which names are used is up to the host.
+    pub sys_calls: HashMap<String, u32>,
+}
+
+impl Config {
+    pub const SECTIONS: [&'static str; 2] = ["abi", "sys_calls"];
+
+    pub const ABI_KEYS: [&'static str; 10] = [
+        "register_count",
+        "system_memory",
+        "memory_size",
+        "device_memory",
+        "system_permissions",
+        "page_size",
+        "interrupt_vector",
+        "stack_size",
+        "stack_address",
+        "entry",
+    ];
+
+    /// Parses a config, settings that aren't in `source` keep their defaults.
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        config.apply(source)?;
+
+        Ok(config)
+    }
+
+    /// Applies the settings in `source` on top of `self`.
+    pub fn apply(&mut self, source: &str) -> Result<(), ConfigError> {
+        let mut section = None;
+
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+
+            let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']').ok_or_else(|| {
+                    ConfigError::new(format!("expected `]` after `{}`", line)).at(number)
+                })?;
+
+                let name = name.trim();
+
+                if !Self::SECTIONS.contains(&name) {
+                    return Err(ConfigError::new(format!(
+                        "unknown section `{}`, expected one of {}",
+                        name,
+                        Self::SECTIONS.join(", ")
+                    ))
+                    .at(number));
+                }
+
+                section = Some(name);
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                ConfigError::new(format!("expected `key = value`, found `{}`", line)).at(number)
+            })?;
+
+            let section = section.ok_or_else(|| {
+                ConfigError::new(format!("`{}` is not in a section", key.trim())).at(number)
+            })?;
+
+            self.set(section, key.trim(), value.trim())
+                .map_err(|error| error.at(number))?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets `key` in `section` to `value`, like the line `key = value` in the
+    /// section would.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+        match section {
+            "abi" => self.set_abi(key, value),
+            "sys_calls" => {
+                let address = parse_u32(key, value)?;
+                self.sys_calls.insert(key.to_owned(), address);
+
+                Ok(())
+            }
+            _ => Err(ConfigError::new(format!(
+                "unknown section `{}`, expected one of {}",
+                section,
+                Self::SECTIONS.join(", ")
+            ))),
+        }
+    }
+
+    /// Returns the address of every sys call a host knows, given as the name,
+    /// the default address and how many addresses it uses.
+    ///
+    /// Fails if the config names an unknown sys call, or if sys calls overlap
+    /// or aren't in system memory.
+    pub fn sys_call_addresses(
+        &self,
+        known: &[(&'static str, u32, u32)],
+    ) -> Result<HashMap<&'static str, u32>, ConfigError> {
+        for name in self.sys_calls.keys() {
+            if !known.iter().any(|(known, ..)| known == name) {
+                let names = known.iter().map(|(name, ..)| *name).collect::<Vec<_>>();
+
+                return Err(ConfigError::new(format!(
+                    "unknown sys call `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )));
+            }
+        }
+
+        let ranges = known
+            .iter()
+            .map(|&(name, default, count)| {
+                let address = self.sys_calls.get(name).copied().unwrap_or(default);
+                (name, address as u64, count as u64)
+            })
+            .collect::<Vec<_>>();
+
+        for (index, &(name, address, count)) in ranges.iter().enumerate() {
+            if address + count > self.abi.system_memory as u64 {
+                return Err(ConfigError::new(format!(
+                    "sys call `{}` at {:#x} is not in system memory of {} bytes",
+                    name, address, self.abi.system_memory
+                )));
+            }
+
+            for &(other, other_address, other_count) in &ranges[..index] {
+                if address < other_address + other_count && other_address < address + count {
+                    return Err(ConfigError::new(format!(
+                        "sys calls `{}` and `{}` overlap",
+                        other, name
+                    )));
+                }
+            }
+        }
+
+        Ok(ranges
+            .into_iter()
+            .map(|(name, address, _)| (name, address as u32))
+            .collect())
+    }
+
+    fn set_abi(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let abi = &mut self.abi;
+
+        match key {
+            "register_count" => abi.register_count = parse_u32(key, value)?,
+            "system_memory" => abi.system_memory = parse_u32(key, value)?,
+            "memory_size" => abi.memory_size = parse_number(key, value)?,
+            "device_memory" => abi.device_memory = parse_u32(key, value)?,
+            "system_permissions" => abi.system_permissions = parse_permissions(value)?,
+            "page_size" => abi.page_size = parse_u32(key, value)?,
+            "interrupt_vector" => abi.interrupt_vector = parse_u32(key, value)?,
+            "stack_size" => abi.stack_size = parse_u32(key, value)?,
+            "stack_address" => abi.stack_address = Some(parse_u32(key, value)?),
+            "entry" => abi.entry = parse_u32(key, value)?,
+            _ => {
+                return Err(ConfigError::new(format!(
+                    "unknown abi setting `{}`, expected one of {}",
+                    key,
+                    Self::ABI_KEYS.join(", ")
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a number like `4096`, `0x1000` or `4K`.
+fn parse_number(key: &str, value: &str) -> Result<u64, ConfigError> {
+    let invalid = || ConfigError::new(format!("invalid number `{}` for `{}`", value, key));
+
+    let (digits, scale) = match value.as_bytes().last() {
+        Some(b'K' | b'k') => (&value[..value.len() - 1], 1 << 10),
+        Some(b'M' | b'm') => (&value[..value.len() - 1], 1 << 20),
+        Some(b'G' | b'g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    let number = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+
+    number
+        .ok()
+        .and_then(|number| number.checked_mul(scale))
+        .ok_or_else(invalid)
+}
+
+fn parse_u32(key: &str, value: &str) -> Result<u32, ConfigError> {
+    let number = parse_number(key, value)?;
+
+    u32::try_from(number).map_err(|_| {
+        ConfigError::new(format!(
+            "`{}` is too large for `{}`, expected at most {}",
+            value,
+            key,
+            u32::MAX
+        ))
+    })
+}
+
+/// Parses permissions like `rwx`, `-` for none.
+fn parse_permissions(value: &str) -> Result<Permissions, ConfigError> {
+    let mut permissions = Permissions::NONE;
+
+    for c in value.chars() {
+        permissions = permissions
+            | match c {
+                'r' => Permissions::READ,
+                'w' => Permissions::WRITE,
+                'x' => Permissions::EXECUTE,
+                '-' => Permissions::NONE,
+                _ => {
+                    return Err(ConfigError::new(format!(
+                        "invalid permissions `{}`, expected a combination of r, w and x",
+                        value
+                    )))
+                }
+            };
+    }
+
+    Ok(permissions)
+}
+
+/// An invalid setting, with the line it's on if it was read from a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    fn at(self, line: usize) -> Self {
+        Self {
+            line: Some(line),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
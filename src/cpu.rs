@@ -1,20 +1,39 @@
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap};
 
-use crate::{Instruction, Memory, Opcode, Program, Register, Word};
+use crate::history::{History, Step};
 
+use crate::{
+    Access, CpuSnapshot, Device, Fault, Instruction, InterruptFrame, InterruptLines, MappedDevice,
+    Memory, MemoryError, Mmu, Opcode, PageTableEntry, Permissions, Program, Register, WatchHit,
+    WatchKind, Watchpoint, Word,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Registers {
     registers: Vec<Word>,
 }
 
 impl Registers {
     pub fn new(count: usize) -> Self {
-        assert!(count > Register::MIN_REGISTERS);
+        assert!(
+            count >= Register::MIN_REGISTERS,
+            "at least {} registers are required",
+            Register::MIN_REGISTERS
+        );
 
         Self {
             registers: vec![Word::default(); count],
         }
     }
 
+    pub(crate) fn from_words(registers: Vec<Word>) -> Self {
+        Self { registers }
+    }
+
+    pub fn words(&self) -> &[Word] {
+        &self.registers
+    }
+
     pub fn read(&self, reg: Register) -> Word {
         self.registers[reg.0 as usize]
     }
@@ -62,11 +81,45 @@ impl Registers {
     }
 }
 
+/// Memory layout and conventions of a [`Cpu`].
+///
+/// Programs are loaded at `system_memory`. [`Cpu::load_program_with_args`]
+/// lays out arguments and environment strings directly after the program,
+/// followed by the stack:
+///
+/// - `argc` words pointing at the arguments, `argv` points at the first
+/// - `envc` words pointing at the environment strings, `envp` points at the first
+/// - the strings, each a word holding its length followed by its bytes padded
+///   to a multiple of 4, like string constants
+///
+/// At entry `eax` holds `argc`, `ebx` `argv`, `ecx` `envc` and `edx` `envp`.
+/// A [`Heap`](crate::Heap) starts `stack_size` bytes after the stack.
+///
+/// Use [`Abi::validate`] or [`Cpu::try_new`] to check an abi before using it.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Abi {
     pub register_count: u32,
     pub system_memory: u32,
-    pub memory_size: u32,
+    /// Size of memory in bytes, at most 4 GiB. Memory is allocated as it is written.
+    pub memory_size: u64,
+    /// Start of the address range reserved for memory mapped devices, after
+    /// system memory. Devices hide the memory they are mapped over, so memory
+    /// may extend into the range, up to all of the 4 GiB address space.
+    pub device_memory: u32,
+    /// Permissions of the first `system_memory` bytes, applied by [`Cpu::load_program`].
+    pub system_permissions: Permissions,
+    /// Size of the pages translated by the [`Mmu`], must be a power of two.
+    pub page_size: u32,
+    /// Address of the interrupt vector table, a word per interrupt line holding
+    /// the address of its handler.
+    pub interrupt_vector: u32,
+    /// Bytes reserved for the stack before the heap.
+    pub stack_size: u32,
+    /// Address of the stack at entry, directly after the program and its
+    /// arguments if `None`.
+    pub stack_address: Option<u32>,
+    /// Offset of the first instruction from the start of the program.
+    pub entry: u32,
 }
 
 impl Default for Abi {
@@ -75,10 +128,207 @@ impl Default for Abi {
             register_count: 16,
             system_memory: 2 << 12,
             memory_size: 2 << 16,
+            device_memory: 0xf000_0000,
+            system_permissions: Permissions::READ | Permissions::EXECUTE,
+            page_size: 2 << 11,
+            interrupt_vector: (2 << 12) - 4 * InterruptLines::COUNT as u32,
+            stack_size: 2 << 13,
+            stack_address: None,
+            entry: 0,
         }
     }
 }
 
+impl Abi {
+    /// The largest number of registers, register operands are a byte.
+    pub const MAX_REGISTERS: u32 = 256;
+
+    /// Checks that the fields are consistent with each other.
+    pub fn validate(&self) -> Result<(), AbiError> {
+        let min = Register::MIN_REGISTERS as u32;
+
+        if self.register_count < min || self.register_count > Self::MAX_REGISTERS {
+            return Err(AbiError::RegisterCount {
+                count: self.register_count,
+            });
+        }
+
+        if self.memory_size > 1 << 32 {
+            return Err(AbiError::MemorySize {
+                size: self.memory_size,
+            });
+        }
+
+        if self.system_memory as u64 > self.memory_size {
+            return Err(AbiError::SystemMemory {
+                size: self.system_memory,
+                memory_size: self.memory_size,
+            });
+        }
+
+        // devices hide the memory behind them, but not the program
+        if self.device_memory < self.system_memory {
+            return Err(AbiError::DeviceMemory {
+                address: self.device_memory,
+                system_memory: self.system_memory,
+            });
+        }
+
+        if !self.page_size.is_power_of_two() || self.page_size <= PageTableEntry::FLAGS {
+            return Err(AbiError::PageSize {
+                size: self.page_size,
+            });
+        }
+
+        let aligned = [
+            ("system memory", self.system_memory),
+            ("interrupt vector", self.interrupt_vector),
+            ("stack size", self.stack_size),
+            ("stack address", self.stack_address.unwrap_or(0)),
+            ("entry", self.entry),
+        ];
+
+        for (field, value) in aligned {
+            if value % Word::SIZE != 0 {
+                return Err(AbiError::Misaligned { field, value });
+            }
+        }
+
+        let vector_end =
+            self.interrupt_vector as u64 + (Word::SIZE * InterruptLines::COUNT as u32) as u64;
+
+        if vector_end > self.memory_size {
+            return Err(AbiError::InterruptVector {
+                address: self.interrupt_vector,
+            });
+        }
+
+        if let Some(address) = self.stack_address {
+            // the stack must be writable, system memory isn't by default
+            if address < self.system_memory || address as u64 >= self.memory_size {
+                return Err(AbiError::StackAddress { address });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Why an [`Abi`] is invalid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbiError {
+    RegisterCount { count: u32 },
+    MemorySize { size: u64 },
+    SystemMemory { size: u32, memory_size: u64 },
+    DeviceMemory { address: u32, system_memory: u32 },
+    PageSize { size: u32 },
+    Misaligned { field: &'static str, value: u32 },
+    InterruptVector { address: u32 },
+    StackAddress { address: u32 },
+}
+
+impl std::fmt::Display for AbiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RegisterCount { count } => write!(
+                f,
+                "{} registers is not supported, expected {} to {}",
+                count,
+                Register::MIN_REGISTERS,
+                Abi::MAX_REGISTERS
+            ),
+            Self::MemorySize { size } => {
+                write!(f, "memory size of {} bytes is larger than 4 GiB", size)
+            }
+            Self::SystemMemory { size, memory_size } => write!(
+                f,
+                "system memory of {} bytes is larger than memory of {} bytes",
+                size, memory_size
+            ),
+            Self::DeviceMemory {
+                address,
+                system_memory,
+            } => write!(
+                f,
+                "device memory at {:#x} overlaps system memory of {} bytes",
+                address, system_memory
+            ),
+            Self::PageSize { size } => write!(
+                f,
+                "page size {} is not a power of two larger than {}",
+                size,
+                PageTableEntry::FLAGS
+            ),
+            Self::Misaligned { field, value } => {
+                write!(f, "{} {:#x} is not a multiple of 4", field, value)
+            }
+            Self::InterruptVector { address } => write!(
+                f,
+                "interrupt vector table at {:#x} doesn't fit in memory",
+                address
+            ),
+            Self::StackAddress { address } => write!(
+                f,
+                "stack at {:#x} is not between system memory and the end of memory",
+                address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AbiError {}
+
+/// Why a program can't be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// The program, arguments or environment don't fit in memory.
+    Memory(MemoryError),
+    /// [`Abi::stack_address`] is before `end`, the end of the program and its
+    /// arguments.
+    StackAddress { address: u32, end: u32 },
+}
+
+impl From<MemoryError> for LoadError {
+    fn from(error: MemoryError) -> Self {
+        Self::Memory(error)
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Memory(error) => error.fmt(f),
+            Self::StackAddress { address, end } => write!(
+                f,
+                "stack at {:#x} overlaps the program and arguments ending at {:#x}",
+                address, end
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Sys calls registered at `count` addresses from `address` that don't fit
+/// below 4 GiB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SysCallError {
+    pub address: u32,
+    pub count: u32,
+}
+
+impl std::fmt::Display for SysCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} sys calls at {:#x} don't fit in the address space",
+            self.count, self.address
+        )
+    }
+}
+
+impl std::error::Error for SysCallError {}
+
 pub struct CpuState<'a> {
     abi: &'a Abi,
     pub registers: &'a mut Registers,
@@ -89,6 +339,27 @@ impl<'a> CpuState<'a> {
     pub fn abi(&self) -> &Abi {
         self.abi
     }
+
+    /// Returns true if the `len` bytes at `ptr` are in memory and allow
+    /// `access`, sys calls check guest buffers with this before using them.
+    pub fn is_allowed(&self, ptr: u32, len: u32, access: Access) -> bool {
+        self.memory.in_bounds(ptr, len) && self.memory.is_allowed(ptr, len, access)
+    }
+}
+
+/// Why the cpu stopped running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The `exit` instruction was executed with the exit code.
+    Exit(u32),
+    Watchpoint(WatchHit),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Trap {
+    pub(crate) eip: u32,
+    pub(crate) address: u32,
+    pub(crate) access: Access,
 }
 
 pub struct Cpu<T = ()> {
@@ -96,6 +367,19 @@ pub struct Cpu<T = ()> {
     registers: Registers,
     memory: Memory,
     sys_calls: HashMap<u32, fn(&mut CpuState, &mut T)>,
+    devices: Vec<MappedDevice>,
+    cycles: u64,
+    supervisor: bool,
+    mmu: Option<Mmu>,
+    trap_handler: u32,
+    trap: Option<Trap>,
+    interrupts: InterruptLines,
+    interrupts_enabled: bool,
+    interrupt: Option<InterruptFrame>,
+    history: History,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_watchpoint: usize,
+    watch_hit: Option<WatchHit>,
 }
 
 impl<T> Default for Cpu<T> {
@@ -105,55 +389,886 @@ impl<T> Default for Cpu<T> {
 }
 
 impl<T> Cpu<T> {
+    /// Creates a cpu, panics if `abi` is invalid.
     pub fn new(abi: Abi) -> Self {
-        Self {
+        Self::try_new(abi).unwrap_or_else(|error| panic!("invalid abi: {}", error))
+    }
+
+    /// Creates a cpu, failing if `abi` is invalid.
+    pub fn try_new(abi: Abi) -> Result<Self, AbiError> {
+        abi.validate()?;
+
+        Ok(Self {
             abi,
             registers: Registers::new(abi.register_count as usize),
-            memory: Memory::with_size(abi.memory_size as usize),
+            memory: Memory::with_size(abi.memory_size),
             sys_calls: HashMap::new(),
-        }
+            devices: Vec::new(),
+            cycles: 0,
+            supervisor: true,
+            mmu: None,
+            trap_handler: 0,
+            trap: None,
+            interrupts: InterruptLines::new(),
+            interrupts_enabled: false,
+            interrupt: None,
+            history: History::default(),
+            watchpoints: Vec::new(),
+            next_watchpoint: 0,
+            watch_hit: None,
+        })
     }
 
     pub fn abi(&self) -> &Abi {
         &self.abi
     }
 
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Returns physical memory, writes through it bypass watchpoints, devices
+    /// and the undo history.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Returns the number of cycles spent executing instructions.
+    ///
+    /// Every instruction costs one cycle, block memory instructions cost an
+    /// additional cycle per word they touch.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns true if the cpu is in supervisor mode.
+    ///
+    /// The cpu starts in supervisor mode, where addresses are physical and
+    /// privileged instructions are allowed. In user mode addresses are
+    /// translated by the [`Mmu`] if a page table is installed.
+    pub fn is_supervisor(&self) -> bool {
+        self.supervisor
+    }
+
+    pub fn mmu(&self) -> Option<&Mmu> {
+        self.mmu.as_ref()
+    }
+
+    /// Returns a handle to the interrupt lines, used to raise interrupts from
+    /// devices or other threads.
+    pub fn interrupt_lines(&self) -> InterruptLines {
+        self.interrupts.clone()
+    }
+
+    pub fn raise_interrupt(&mut self, line: u8) {
+        self.interrupts.raise(line);
+    }
+
+    /// Returns true if interrupts are enabled, they start disabled and are
+    /// enabled with `ei`.
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupts_enabled
+    }
+
+    /// Creates a copy of the cpu that shares memory pages with `self` until
+    /// either of them writes to a page.
+    ///
+    /// Sys calls are shared, devices are forked with [`Device::fork`].
+    pub fn fork(&self) -> Self {
+        let devices = self
+            .devices
+            .iter()
+            .filter_map(|mapped| {
+                Some(MappedDevice {
+                    address: mapped.address,
+                    size: mapped.size,
+                    device: mapped.device.fork()?,
+                })
+            })
+            .collect();
+
+        let interrupts = InterruptLines::new();
+        interrupts.set_pending(self.interrupts.pending());
+
+        Self {
+            abi: self.abi,
+            registers: self.registers.clone(),
+            memory: self.memory.clone(),
+            sys_calls: self.sys_calls.clone(),
+            devices,
+            cycles: self.cycles,
+            supervisor: self.supervisor,
+            mmu: self.mmu.clone(),
+            trap_handler: self.trap_handler,
+            trap: self.trap,
+            interrupts,
+            interrupts_enabled: self.interrupts_enabled,
+            interrupt: self.interrupt,
+            history: History::default(),
+            watchpoints: self.watchpoints.clone(),
+            next_watchpoint: self.next_watchpoint,
+            watch_hit: None,
+        }
+    }
+
+    /// Captures registers, memory and the abi, devices are not included.
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            abi: self.abi,
+            registers: self.registers.clone(),
+            memory: self.memory.clone(),
+            cycles: self.cycles,
+            supervisor: self.supervisor,
+            page_table: self.mmu.as_ref().map(|mmu| (mmu.table(), mmu.len())),
+            trap_handler: self.trap_handler,
+            trap: self.trap,
+            interrupts: self.interrupts.pending(),
+            interrupts_enabled: self.interrupts_enabled,
+            interrupt: self.interrupt,
+        }
+    }
+
+    /// Restores the state captured by [`Cpu::snapshot`], sys calls and devices
+    /// are kept as they are.
+    pub fn restore(&mut self, snapshot: &CpuSnapshot) {
+        self.abi = snapshot.abi;
+        self.registers = snapshot.registers.clone();
+        self.memory = snapshot.memory.clone();
+        self.cycles = snapshot.cycles;
+        self.supervisor = snapshot.supervisor;
+        self.mmu = snapshot
+            .page_table
+            .map(|(table, len)| Mmu::new(self.abi.page_size, table, len));
+        self.trap_handler = snapshot.trap_handler;
+        self.trap = snapshot.trap;
+        self.interrupts.set_pending(snapshot.interrupts);
+        self.interrupts_enabled = snapshot.interrupts_enabled;
+        self.interrupt = snapshot.interrupt;
+        self.history.clear();
+    }
+
+    /// Adds a watchpoint, returning an id for [`Cpu::remove_watchpoint`].
+    ///
+    /// Loads, stores and block memory instructions accessing watched bytes
+    /// stop the cpu after the instruction with [`Stop::Watchpoint`].
+    pub fn add_watchpoint(&mut self, address: u32, len: u32, kind: WatchKind) -> usize {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+
+        self.watchpoints
+            .push((id, Watchpoint::new(address, len, kind)));
+
+        id
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> Option<Watchpoint> {
+        let index = self
+            .watchpoints
+            .iter()
+            .position(|&(other, _)| other == id)?;
+        Some(self.watchpoints.remove(index).1)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    /// Sets the number of instructions that are recorded so they can be undone
+    /// with [`Cpu::step_back`], `0` disables recording.
+    ///
+    /// Writes to devices are not recorded and can't be undone.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    /// Returns the number of instructions that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    fn undo(&mut self, step: Step) {
+        if let Some(memory) = step.memory {
+            self.memory = memory;
+        }
+
+        for (ptr, bytes) in step.writes.into_iter().rev() {
+            let _ = self.memory.write_bytes(ptr, &bytes);
+        }
+
+        self.registers = step.registers;
+        self.cycles = step.cycles;
+        self.supervisor = step.supervisor;
+        self.mmu = step
+            .page_table
+            .map(|(table, len)| Mmu::new(self.abi.page_size, table, len));
+        self.trap_handler = step.trap_handler;
+        self.trap = step.trap;
+        self.interrupts.set_pending(step.interrupts);
+        self.interrupts_enabled = step.interrupts_enabled;
+        self.interrupt = step.interrupt;
+    }
+
+    /// Undoes the last executed instruction, returns false if there is no
+    /// recorded instruction to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop() {
+            Some(step) => {
+                self.undo(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Steps back until just before the last instruction that wrote to
+    /// `address`, `eip` then points at that instruction.
+    ///
+    /// Returns false if no recorded instruction wrote to `address`, in which
+    /// case the cpu is at the oldest recorded state.
+    pub fn reverse_to_write(&mut self, address: u32) -> bool {
+        while let Some(step) = self.history.pop() {
+            let wrote = step.writes_to(address, &self.memory);
+
+            self.undo(step);
+
+            if wrote {
+                return true;
+            }
+        }
+
+        false
+    }
+
     pub fn register_sys_call(&mut self, address: u32, call: fn(&mut CpuState, &mut T)) {
         self.sys_calls.insert(address, call);
     }
 
-    pub fn push_stack(&mut self, data: Word) {
+    /// Registers `calls` at consecutive addresses starting at `address`.
+    pub fn register_sys_calls(
+        &mut self,
+        address: u32,
+        calls: &[fn(&mut CpuState, &mut T)],
+    ) -> Result<(), SysCallError> {
+        let error = SysCallError {
+            address,
+            count: calls.len() as u32,
+        };
+
+        if address as u64 + calls.len() as u64 > 1 << 32 {
+            return Err(error);
+        }
+
+        for (offset, &call) in calls.iter().enumerate() {
+            self.register_sys_call(address + offset as u32, call);
+        }
+
+        Ok(())
+    }
+
+    /// Maps `device` to the `size` bytes starting at `address`.
+    ///
+    /// Loads and stores to mapped addresses are routed to the device instead
+    /// of memory.
+    pub fn map_device(&mut self, address: u32, size: u32, device: impl Device + 'static) {
+        assert!(
+            !self
+                .devices
+                .iter()
+                .any(|mapped| mapped.overlaps(address, size)),
+            "device at {:#x} overlaps an already mapped device",
+            address
+        );
+
+        self.devices.push(MappedDevice {
+            address,
+            size,
+            device: Box::new(device),
+        });
+    }
+
+    fn mapped_device(&mut self, ptr: u32, len: u32) -> Option<&mut MappedDevice> {
+        self.devices
+            .iter_mut()
+            .find(|mapped| mapped.contains(ptr, len))
+    }
+
+    pub fn push_stack(&mut self, data: Word) -> Result<(), Fault> {
         let esp = self.registers.esp().to_u32();
 
-        self.memory.write(data, esp, Word::WIDTH);
+        self.write_memory(data, esp, Word::WIDTH)?;
 
         self.registers
-            .write_esp(Word::from_u32(esp + Word::WIDTH as u32));
+            .write_esp(Word::from_u32(esp.wrapping_add(Word::SIZE)));
+
+        Ok(())
     }
 
-    pub fn pop_stack(&mut self) -> Word {
-        let esp = self.registers.esp().to_u32() - Word::SIZE;
+    pub fn pop_stack(&mut self) -> Result<Word, Fault> {
+        let esp = self.registers.esp().to_u32().wrapping_sub(Word::SIZE);
+
+        let data = self.read_memory(esp, Word::WIDTH)?;
         self.registers.write_esp(Word::from_u32(esp));
 
-        self.memory.read(esp, Word::WIDTH).unwrap()
+        Ok(data)
+    }
+
+    fn check_access(&self, ptr: u32, len: u32, access: Access) -> Result<(), Fault> {
+        if self.memory.is_allowed(ptr, len, access) {
+            Ok(())
+        } else {
+            Err(Fault::Protection {
+                address: ptr,
+                access,
+            })
+        }
+    }
+
+    fn check_bounds(&self, ptr: u32, len: u32) -> Result<(), Fault> {
+        if self.memory.in_bounds(ptr, len) {
+            Ok(())
+        } else {
+            Err(Fault::OutOfBounds { address: ptr, len })
+        }
+    }
+
+    fn page_size(&self) -> Option<u32> {
+        match self.mmu {
+            Some(ref mmu) if !self.supervisor => Some(mmu.page_size()),
+            _ => None,
+        }
+    }
+
+    fn translate(&mut self, ptr: u32, access: Access) -> Result<u32, Fault> {
+        match self.mmu {
+            Some(ref mut mmu) if !self.supervisor => mmu.translate(&self.memory, ptr, access),
+            _ => Ok(ptr),
+        }
+    }
+
+    /// Splits the `len` bytes at `ptr` into physically contiguous ranges.
+    fn physical_ranges(
+        &mut self,
+        ptr: u32,
+        len: u32,
+        access: Access,
+    ) -> Result<Vec<(u32, u32)>, Fault> {
+        let page_size = match self.page_size() {
+            Some(page_size) => page_size,
+            None => return Ok(vec![(ptr, len)]),
+        };
+
+        let mut ranges = Vec::new();
+        let mut offset = 0;
+
+        while offset < len {
+            let virt = ptr.wrapping_add(offset);
+            let chunk = (page_size - virt % page_size).min(len - offset);
+
+            ranges.push((self.translate(virt, access)?, chunk));
+            offset += chunk;
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns the physical address of `ptr` if the `len` bytes at `ptr` are
+    /// on a single page.
+    fn translate_contiguous(
+        &mut self,
+        ptr: u32,
+        len: u32,
+        access: Access,
+    ) -> Result<Option<u32>, Fault> {
+        match self.page_size() {
+            Some(page_size) if ptr % page_size + len > page_size => Ok(None),
+            _ => self.translate(ptr, access).map(Some),
+        }
+    }
+
+    fn check_width(width: u8) -> Result<(), Fault> {
+        match width {
+            1 | 2 | 4 => Ok(()),
+            _ => Err(Fault::InvalidWidth { width }),
+        }
+    }
+
+    fn read_physical(&mut self, ptr: u32, width: u8, access: Access) -> Result<Word, Fault> {
+        Self::check_width(width)?;
+
+        if let Some(mapped) = self.mapped_device(ptr, width as u32) {
+            let offset = ptr - mapped.address;
+            return Ok(mapped.device.read(offset, width));
+        }
+
+        self.check_access(ptr, width as u32, access)?;
+
+        Ok(self.memory.read(ptr, width)?)
+    }
+
+    fn write_physical(&mut self, data: Word, ptr: u32, width: u8) -> Result<(), Fault> {
+        Self::check_width(width)?;
+
+        let mapped = self
+            .devices
+            .iter_mut()
+            .find(|mapped| mapped.contains(ptr, width as u32));
+
+        if let Some(mapped) = mapped {
+            let offset = ptr - mapped.address;
+            mapped.device.write(offset, data, width);
+
+            // the device may write anywhere in memory, keep the old memory
+            // in case it does
+            let old = self.history.is_recording().then(|| self.memory.clone());
+
+            if mapped.device.dma(&mut self.memory) {
+                if let Some(old) = old {
+                    self.history.record_memory(&old);
+                }
+            }
+
+            return Ok(());
+        }
+
+        self.check_access(ptr, width as u32, Access::Write)?;
+
+        self.history.record_write(&self.memory, ptr, width as u32);
+
+        Ok(self.memory.write(data, ptr, width)?)
+    }
+
+    fn read_word(&mut self, ptr: u32, width: u8, access: Access) -> Result<Word, Fault> {
+        if let Some(ptr) = self.translate_contiguous(ptr, width as u32, access)? {
+            return self.read_physical(ptr, width, access);
+        }
+
+        if width > Word::WIDTH {
+            return Err(Fault::OutOfBounds {
+                address: ptr,
+                len: width as u32,
+            });
+        }
+
+        // the word is split across pages, read it one byte at a time
+        let mut bytes = [0; 4];
+        let mut index = (Word::WIDTH - width) as usize;
+
+        for (ptr, len) in self.physical_ranges(ptr, width as u32, access)? {
+            for ptr in ptr..ptr + len {
+                bytes[index] = self.read_physical(ptr, 1, access)?.to_bytes()[3];
+                index += 1;
+            }
+        }
+
+        Ok(Word::from_bytes(bytes))
+    }
+
+    fn fetch(&mut self, ptr: u32) -> Result<Word, Fault> {
+        self.read_word(ptr, Word::WIDTH, Access::Execute)
+    }
+
+    /// Returns the first watched range among the `len` bytes at `ptr`.
+    fn watched(&self, ptr: u32, len: u32, access: Access) -> Option<(u32, u32)> {
+        if self.watch_hit.is_some() {
+            return None;
+        }
+
+        self.watchpoints
+            .iter()
+            .filter(|(_, watchpoint)| watchpoint.kind.matches(access))
+            .find_map(|(_, watchpoint)| watchpoint.overlap(ptr, len))
+            .map(|(address, len)| (address, len.min(Word::SIZE)))
+    }
+
+    /// Reads memory without faulting or touching devices, for reporting old
+    /// values of watched writes.
+    fn peek(&mut self, ptr: u32, len: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for index in 0..len {
+            let byte = self
+                .translate(ptr.wrapping_add(index), Access::Read)
+                .ok()
+                .and_then(|ptr| self.memory.read(ptr, 1).ok())
+                .map_or(0, |word| word.to_bytes()[3]);
+
+            bytes.push(byte);
+        }
+
+        bytes
+    }
+
+    fn watch_hit(&mut self, address: u32, access: Access, old: &[u8], new: &[u8]) {
+        let word = |bytes: &[u8]| {
+            let mut word = [0; 4];
+            word[4 - bytes.len()..].copy_from_slice(bytes);
+            Word::from_bytes(word)
+        };
+
+        self.watch_hit = Some(WatchHit {
+            eip: self.registers.eip().to_u32(),
+            address,
+            len: old.len() as u32,
+            access,
+            old: word(old),
+            new: word(new),
+        });
+    }
+
+    fn read_memory(&mut self, ptr: u32, width: u8) -> Result<Word, Fault> {
+        let data = self.read_word(ptr, width, Access::Read)?;
+
+        if let Some((address, len)) = self.watched(ptr, width as u32, Access::Read) {
+            let offset = (Word::WIDTH - width) as usize + (address - ptr) as usize;
+            let bytes = &data.to_bytes()[offset..offset + len as usize];
+
+            self.watch_hit(address, Access::Read, bytes, bytes);
+        }
+
+        Ok(data)
+    }
+
+    fn write_memory(&mut self, data: Word, ptr: u32, width: u8) -> Result<(), Fault> {
+        if let Some((address, len)) = self.watched(ptr, width as u32, Access::Write) {
+            let old = self.peek(address, len);
+
+            self.write_word(data, ptr, width)?;
+
+            let offset = (Word::WIDTH - width) as usize + (address - ptr) as usize;
+            let new = &data.to_bytes()[offset..offset + len as usize];
+
+            self.watch_hit(address, Access::Write, &old, new);
+
+            return Ok(());
+        }
+
+        self.write_word(data, ptr, width)
     }
 
-    pub fn load_program(&mut self, program: &Program) {
+    fn write_word(&mut self, data: Word, ptr: u32, width: u8) -> Result<(), Fault> {
+        if let Some(ptr) = self.translate_contiguous(ptr, width as u32, Access::Write)? {
+            return self.write_physical(data, ptr, width);
+        }
+
+        if width > Word::WIDTH {
+            return Err(Fault::OutOfBounds {
+                address: ptr,
+                len: width as u32,
+            });
+        }
+
+        // the word is split across pages, write it one byte at a time
+        let bytes = data.to_bytes();
+        let mut index = (Word::WIDTH - width) as usize;
+
+        for (ptr, len) in self.physical_ranges(ptr, width as u32, Access::Write)? {
+            for ptr in ptr..ptr + len {
+                self.write_physical(Word::from_bytes([0, 0, 0, bytes[index]]), ptr, 1)?;
+                index += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_memory_bytes(&mut self, ptr: u32, len: u32) -> Result<Vec<u8>, Fault> {
+        let mut bytes = Vec::new();
+
+        for (ptr, len) in self.physical_ranges(ptr, len, Access::Read)? {
+            self.check_access(ptr, len, Access::Read)?;
+
+            bytes.extend_from_slice(&self.memory.read_bytes(ptr, len)?);
+        }
+
+        if let Some((address, len)) = self.watched(ptr, len, Access::Read) {
+            let offset = (address - ptr) as usize;
+            let watched = bytes[offset..offset + len as usize].to_vec();
+
+            self.watch_hit(address, Access::Read, &watched, &watched);
+        }
+
+        Ok(bytes)
+    }
+
+    fn write_memory_bytes(&mut self, ptr: u32, bytes: &[u8]) -> Result<(), Fault> {
+        if let Some((address, len)) = self.watched(ptr, bytes.len() as u32, Access::Write) {
+            let old = self.peek(address, len);
+
+            self.write_bytes(ptr, bytes)?;
+
+            let offset = (address - ptr) as usize;
+            let new = &bytes[offset..offset + len as usize];
+
+            self.watch_hit(address, Access::Write, &old, new);
+
+            return Ok(());
+        }
+
+        self.write_bytes(ptr, bytes)
+    }
+
+    /// Checks that the `len` bytes at `ptr` can be accessed without accessing
+    /// them.
+    fn check_range(&mut self, ptr: u32, len: u32, access: Access) -> Result<(), Fault> {
+        for (ptr, len) in self.physical_ranges(ptr, len, access)? {
+            self.check_access(ptr, len, access)?;
+            self.check_bounds(ptr, len)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, ptr: u32, bytes: &[u8]) -> Result<(), Fault> {
+        let ranges = self.physical_ranges(ptr, bytes.len() as u32, Access::Write)?;
+
+        // check every range before writing to any of them
+        for &(ptr, len) in &ranges {
+            self.check_access(ptr, len, Access::Write)?;
+            self.check_bounds(ptr, len)?;
+        }
+
+        let mut offset = 0;
+
+        for (ptr, len) in ranges {
+            self.history.record_write(&self.memory, ptr, len);
+
+            let len = len as usize;
+
+            self.memory.write_bytes(ptr, &bytes[offset..offset + len])?;
+
+            offset += len;
+        }
+
+        Ok(())
+    }
+
+    /// Loads `program` after the system memory and protects memory, the system
+    /// memory gets [`Abi::system_permissions`], the program is readable and
+    /// executable and the rest of memory is readable and writable.
+    ///
+    /// Fails if [`Abi::stack_address`] is in the program.
+    pub fn load_program(&mut self, program: &Program) -> Result<(), LoadError> {
+        let code_end = self.abi.system_memory + program.len();
+        self.check_stack(code_end)?;
+
         self.memory
-            .write_bytes(self.abi.system_memory, program.bytes());
+            .write_bytes(self.abi.system_memory, program.bytes())?;
 
-        self.registers
-            .write_eip(Word::from_u32(self.abi.system_memory));
+        self.memory
+            .protect(0, self.abi.system_memory, self.abi.system_permissions);
+        self.memory.protect(
+            self.abi.interrupt_vector,
+            Word::SIZE * InterruptLines::COUNT as u32,
+            Permissions::READ | Permissions::WRITE,
+        );
+        self.memory.protect(
+            self.abi.system_memory,
+            program.len(),
+            Permissions::READ | Permissions::EXECUTE,
+        );
+        self.memory.protect(
+            code_end,
+            self.abi.memory_size.saturating_sub(code_end as u64) as u32,
+            Permissions::READ | Permissions::WRITE,
+        );
 
         self.registers
-            .write_esp(Word::from_u32(self.abi.system_memory + program.len()));
+            .write_eip(Word::from_u32(self.abi.system_memory + self.abi.entry));
+
+        let stack = self.abi.stack_address.unwrap_or(code_end);
+        self.registers.write_esp(Word::from_u32(stack));
 
         self.registers
             .write_ebp(Word::from_u32(self.abi.system_memory));
+
+        Ok(())
+    }
+
+    /// Checks that [`Abi::stack_address`] isn't before `end`, the stack grows
+    /// up so it would overwrite what is there.
+    fn check_stack(&self, end: u32) -> Result<(), LoadError> {
+        match self.abi.stack_address {
+            Some(address) if address < end => Err(LoadError::StackAddress { address, end }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Loads `program` and lays out `args` and `env` after it as described in
+    /// [`Abi`].
+    ///
+    /// Fails if [`Abi::stack_address`] is in the program, arguments or
+    /// environment.
+    pub fn load_program_with_args<S: AsRef<str>>(
+        &mut self,
+        program: &Program,
+        args: &[S],
+        env: &[S],
+    ) -> Result<(), LoadError> {
+        self.load_program(program)?;
+
+        let argv = self.abi.system_memory + program.len();
+        let envp = argv + args.len() as u32 * Word::SIZE;
+        let mut string_ptr = envp + env.len() as u32 * Word::SIZE;
+
+        let strings = args.iter().chain(env).map(AsRef::as_ref);
+
+        for (index, string) in strings.enumerate() {
+            let pointer = argv + index as u32 * Word::SIZE;
+            self.memory
+                .write(Word::from_u32(string_ptr), pointer, Word::WIDTH)?;
+
+            let len = string.len() as u32;
+            self.memory
+                .write(Word::from_u32(len), string_ptr, Word::WIDTH)?;
+            self.memory.write_string(string_ptr + Word::SIZE, string)?;
+
+            string_ptr += Word::SIZE + len.div_ceil(Word::SIZE) * Word::SIZE;
+        }
+
+        self.check_stack(string_ptr)?;
+
+        self.registers
+            .write(Register::EAX, Word::from_u32(args.len() as u32));
+        self.registers.write(Register::EBX, Word::from_u32(argv));
+        self.registers
+            .write(Register::ECX, Word::from_u32(env.len() as u32));
+        self.registers.write(Register::EDX, Word::from_u32(envp));
+
+        let stack = self.abi.stack_address.unwrap_or(string_ptr);
+        self.registers.write_esp(Word::from_u32(stack));
+
+        Ok(())
+    }
+
+    /// Bytes block instructions access at a time, so the host never holds
+    /// more than this of a block in memory.
+    const BLOCK_CHUNK: u32 = 4096;
+
+    fn block_cost(len: u32) -> u64 {
+        (len as u64).div_ceil(Word::SIZE as u64)
+    }
+
+    /// Executes a single instruction, returns `Some` if the cpu stopped.
+    pub fn eval_instruction(&mut self, state: &mut T) -> Result<Option<Stop>, Fault> {
+        for mapped in &mut self.devices {
+            if let Some(line) = mapped.device.poll(self.cycles) {
+                self.interrupts.raise(line);
+            }
+        }
+
+        let interrupt = self.pending_interrupt();
+
+        let eip = match interrupt {
+            Some((_, handler)) => handler,
+            None => self.registers.eip().to_u32(),
+        };
+
+        if self.history.limit() > 0 {
+            // sys calls may write anywhere so keep all of memory, pages are shared
+            let memory = self
+                .sys_calls
+                .contains_key(&eip)
+                .then(|| self.memory.clone());
+
+            self.history.current = Some(Step {
+                registers: self.registers.clone(),
+                cycles: self.cycles,
+                supervisor: self.supervisor,
+                page_table: self.mmu.as_ref().map(|mmu| (mmu.table(), mmu.len())),
+                trap_handler: self.trap_handler,
+                trap: self.trap,
+                interrupts: self.interrupts.pending(),
+                interrupts_enabled: self.interrupts_enabled,
+                interrupt: self.interrupt,
+                writes: Vec::new(),
+                memory,
+            });
+        }
+
+        if let Some((line, _)) = interrupt {
+            self.deliver_interrupt(line, eip);
+        }
+
+        let result = self.execute_instruction(state, eip);
+        self.history.finish();
+
+        // report watchpoints hit by instructions that completed
+        let watch_hit = self.watch_hit.take();
+
+        match result {
+            Ok(None) if watch_hit.is_some() => {
+                Ok(watch_hit.map(|hit| Stop::Watchpoint(WatchHit { eip, ..hit })))
+            }
+            Err(Fault::PageFault { address, access })
+                if !self.supervisor && self.trap_handler != 0 =>
+            {
+                // deliver the page fault to the trap handler, tret retries the instruction
+                self.trap = Some(Trap {
+                    eip,
+                    address,
+                    access,
+                });
+                self.supervisor = true;
+                self.registers.write_eip(Word::from_u32(self.trap_handler));
+
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the lowest pending interrupt line and its handler, if an
+    /// interrupt should be delivered before the next instruction.
+    ///
+    /// Lines without a handler are dropped.
+    fn pending_interrupt(&mut self) -> Option<(u8, u32)> {
+        if !self.interrupts_enabled || self.interrupt.is_some() {
+            return None;
+        }
+
+        loop {
+            let pending = self.interrupts.pending();
+
+            if pending == 0 {
+                return None;
+            }
+
+            let line = pending.trailing_zeros() as u8;
+            let entry = self.abi.interrupt_vector + line as u32 * Word::SIZE;
+
+            match self.memory.read(entry, Word::WIDTH).map(Word::to_u32) {
+                Ok(handler) if handler != 0 => return Some((line, handler)),
+                _ => self.interrupts.clear(line),
+            }
+        }
+    }
+
+    fn deliver_interrupt(&mut self, line: u8, handler: u32) {
+        self.interrupts.clear(line);
+
+        // save the interrupted state and enter the handler in supervisor mode
+        self.interrupt = Some(InterruptFrame {
+            eip: self.registers.eip().to_u32(),
+            erp: self.registers.erp().to_u32(),
+            supervisor: self.supervisor,
+        });
+        self.interrupts_enabled = false;
+        self.supervisor = true;
+        self.registers.write_eip(Word::from_u32(handler));
     }
 
-    pub fn eval_instruction(&mut self, state: &mut T) -> bool {
-        let eip = self.registers.eip().to_u32();
+    fn execute_instruction(&mut self, state: &mut T, eip: u32) -> Result<Option<Stop>, Fault> {
+        self.cycles += 1;
 
         if let Some(sys_call) = self.sys_calls.get(&eip) {
             let mut cpu_state = CpuState {
@@ -169,93 +1284,321 @@ impl<T> Cpu<T> {
             let erp = self.registers.erp();
             self.registers.write_eip(erp);
 
-            return true;
+            return Ok(None);
         }
 
-        let ins = Instruction::from_word(self.memory.read(eip, Word::WIDTH).unwrap());
+        let ins = Instruction::from_word(self.fetch(eip)?);
+
+        if !self.supervisor && ins.opcode.is_privileged() {
+            return Err(Fault::Privileged { opcode: ins.opcode });
+        }
+
+        self.registers
+            .write_eip(Word::from_u32(eip.wrapping_add(ins.opcode.size())));
+
+        match ins.opcode {
+            Opcode::CONST => {
+                let dst: Register = ins.arg(0);
+
+                // read data
+                let data = self.fetch(eip.wrapping_add(Word::SIZE))?;
+
+                // write data
+                self.registers.write(dst, data);
+            }
+            Opcode::MOV => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+
+                // write %src to %dst
+                let data = self.registers.read(src);
+                self.registers.write(dst, data);
+            }
+            Opcode::PUSH => {
+                let src: Register = ins.arg(0);
+
+                // read %src
+                let data = self.registers.read(src);
+
+                self.push_stack(data)?;
+            }
+            Opcode::POP => {
+                let dst: Register = ins.arg(0);
+
+                let data = self.pop_stack()?;
+
+                self.registers.write(dst, data);
+            }
+            Opcode::LOAD => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+                let width: u8 = ins.arg(2);
+
+                // load %src and read data @src
+                let ptr = self.registers.read(src).to_u32();
+                let data = self.read_memory(ptr, width)?;
+
+                // write data to %dst
+                self.registers.write(dst, data);
+            }
+            Opcode::STORE => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+                let width: u8 = ins.arg(2);
+
+                // read %src and %dst
+                let data = self.registers.read(src);
+                let ptr = self.registers.read(dst).to_u32();
+
+                // write %src to @dst
+                self.write_memory(data, ptr, width)?;
+            }
+            Opcode::LOADS => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+                let width: u8 = ins.arg(2);
+
+                // load %src and read data @src
+                let ptr = self.registers.read(src).to_u32();
+                let data = self.read_memory(ptr, width)?.to_u32();
+
+                // sign extend data from width bytes
+                let shift = 32 - width as u32 * 8;
+                let data = (data << shift) as i32 >> shift;
+
+                // write data to %dst
+                self.registers.write(dst, Word::from_i32(data));
+            }
+            Opcode::MEMCPY => {
+                let dst: Register = ins.arg(0);
+                let src: Register = ins.arg(1);
+                let len: Register = ins.arg(2);
+
+                // read %dst, %src and %len
+                let dst = self.registers.read(dst).to_u32();
+                let src = self.registers.read(src).to_u32();
+                let len = self.registers.read(len).to_u32();
+
+                self.cycles += Self::block_cost(len);
+
+                // no bytes are touched without a length, wherever the pointers are
+                if len > 0 {
+                    self.check_range(src, len, Access::Read)?;
+                    self.check_range(dst, len, Access::Write)?;
+                }
+
+                // copy %len bytes from @src to @dst a chunk at a time, backwards
+                // if @dst is after @src so overlapping bytes are read first
+                let chunks = len.div_ceil(Self::BLOCK_CHUNK);
+
+                for index in 0..chunks {
+                    let index = if dst > src { chunks - 1 - index } else { index };
+                    let offset = index * Self::BLOCK_CHUNK;
+                    let chunk = Self::BLOCK_CHUNK.min(len - offset);
+
+                    let bytes = self.read_memory_bytes(src.wrapping_add(offset), chunk)?;
+                    self.write_memory_bytes(dst.wrapping_add(offset), &bytes)?;
+                }
+            }
+            Opcode::MEMSET => {
+                let dst: Register = ins.arg(0);
+                let val: Register = ins.arg(1);
+                let len: Register = ins.arg(2);
+
+                // read %dst, %val and %len
+                let dst = self.registers.read(dst).to_u32();
+                let val = self.registers.read(val).to_u32();
+                let len = self.registers.read(len).to_u32();
+
+                self.cycles += Self::block_cost(len);
+
+                // no bytes are touched without a length, wherever the pointer is
+                if len > 0 {
+                    self.check_range(dst, len, Access::Write)?;
+                }
+
+                // write %len copies of the low byte of %val to @dst a chunk at a time
+                let bytes = vec![val as u8; Self::BLOCK_CHUNK.min(len) as usize];
+
+                for offset in (0..len).step_by(Self::BLOCK_CHUNK as usize) {
+                    let chunk = Self::BLOCK_CHUNK.min(len - offset) as usize;
+                    self.write_memory_bytes(dst.wrapping_add(offset), &bytes[..chunk])?;
+                }
+            }
+            Opcode::MEMCMP => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let len: Register = ins.arg(2);
+
+                // read dst from the next word
+                let dst = Register::new(self.fetch(eip.wrapping_add(Word::SIZE))?.to_bytes()[0]);
+
+                // read %lhs, %rhs and %len
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
+                let len = self.registers.read(len).to_u32();
+
+                self.cycles += Self::block_cost(len);
+
+                // no bytes are touched without a length, wherever the pointers are
+                if len > 0 {
+                    self.check_range(lhs, len, Access::Read)?;
+                    self.check_range(rhs, len, Access::Read)?;
+                }
+
+                // compare %len bytes of @lhs and @rhs a chunk at a time
+                let mut ordering = Ordering::Equal;
+
+                for offset in (0..len).step_by(Self::BLOCK_CHUNK as usize) {
+                    let chunk = Self::BLOCK_CHUNK.min(len - offset);
+
+                    let lhs = self.read_memory_bytes(lhs.wrapping_add(offset), chunk)?;
+                    let rhs = self.read_memory_bytes(rhs.wrapping_add(offset), chunk)?;
+
+                    ordering = lhs.cmp(&rhs);
+
+                    if ordering.is_ne() {
+                        break;
+                    }
+                }
+
+                // write the ordering to %dst
+                self.registers.write(dst, Word::from_i32(ordering as i32));
+            }
+            Opcode::JMP => {
+                let trg: Register = ins.arg(0);
+
+                // read %trg
+                let ptr = self.registers.read(trg);
+
+                // write %trg to eip
+                self.registers.write_eip(ptr);
+            }
+            Opcode::JMP_NZ => {
+                let trg: Register = ins.arg(0);
+                let src: Register = ins.arg(1);
+
+                // read %src
+                let data = self.registers.read(src).to_u32();
 
-        if ins.opcode != Opcode::CONST {
-            self.registers.write_eip(Word::from_u32(eip + Word::SIZE));
-        }
+                if data != 0 {
+                    // read %trg
+                    let trg = self.registers.read(trg);
 
-        match ins.opcode {
-            Opcode::CONST => {
-                let dst: Register = ins.arg(0);
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
+            }
+            Opcode::JMP_Z => {
+                let trg: Register = ins.arg(0);
+                let src: Register = ins.arg(1);
 
-                // read data
-                let data = self.memory.read(eip + Word::SIZE, Word::WIDTH).unwrap();
+                // read %src
+                let data = self.registers.read(src).to_u32();
 
-                // write data
-                self.registers.write(dst, data);
+                if data == 0 {
+                    // read %trg
+                    let trg = self.registers.read(trg);
 
-                // increment eip twice (to jump over the data)
-                self.registers
-                    .write_eip(Word::from_u32(eip + Word::SIZE * 2));
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
             }
-            Opcode::MOV => {
-                let src: Register = ins.arg(0);
-                let dst: Register = ins.arg(1);
+            Opcode::BEQ => {
+                let trg: Register = ins.arg(0);
+                let lhs: Register = ins.arg(1);
+                let rhs: Register = ins.arg(2);
 
-                // write %src to %dst
-                let data = self.registers.read(src);
-                self.registers.write(dst, data);
-            }
-            Opcode::PUSH => {
-                let src: Register = ins.arg(0);
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
 
-                // read %src
-                let data = self.registers.read(src);
+                if lhs == rhs {
+                    // read %trg
+                    let trg = self.registers.read(trg);
 
-                self.push_stack(data);
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
             }
-            Opcode::POP => {
-                let dst: Register = ins.arg(0);
+            Opcode::BNE => {
+                let trg: Register = ins.arg(0);
+                let lhs: Register = ins.arg(1);
+                let rhs: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
 
-                let data = self.pop_stack();
+                if lhs != rhs {
+                    // read %trg
+                    let trg = self.registers.read(trg);
 
-                self.registers.write(dst, data);
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
             }
-            Opcode::LOAD => {
-                let src: Register = ins.arg(0);
-                let dst: Register = ins.arg(1);
-                let width: u8 = ins.arg(2);
+            Opcode::BLT => {
+                let trg: Register = ins.arg(0);
+                let lhs: Register = ins.arg(1);
+                let rhs: Register = ins.arg(2);
 
-                // load %src and read data @src
-                let ptr = self.registers.read(src).to_u32();
-                let data = self.memory.read(ptr, width).unwrap();
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_i32();
+                let rhs = self.registers.read(rhs).to_i32();
 
-                // write data to %dst
-                self.registers.write(dst, data);
+                if lhs < rhs {
+                    // read %trg
+                    let trg = self.registers.read(trg);
+
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
             }
-            Opcode::STORE => {
-                let src: Register = ins.arg(0);
-                let dst: Register = ins.arg(1);
-                let width: u8 = ins.arg(2);
+            Opcode::BGE => {
+                let trg: Register = ins.arg(0);
+                let lhs: Register = ins.arg(1);
+                let rhs: Register = ins.arg(2);
 
-                // read %src and %dst
-                let data = self.registers.read(src);
-                let ptr = self.registers.read(dst).to_u32();
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_i32();
+                let rhs = self.registers.read(rhs).to_i32();
 
-                // write %src to @dst
-                self.memory.write(data, ptr, width);
+                if lhs >= rhs {
+                    // read %trg
+                    let trg = self.registers.read(trg);
+
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
             }
-            Opcode::JMP => {
+            Opcode::BLTU => {
                 let trg: Register = ins.arg(0);
+                let lhs: Register = ins.arg(1);
+                let rhs: Register = ins.arg(2);
 
-                // read %trg
-                let ptr = self.registers.read(trg);
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
 
-                // write %trg to eip
-                self.registers.write_eip(ptr);
+                if lhs < rhs {
+                    // read %trg
+                    let trg = self.registers.read(trg);
+
+                    // write %trg to eip
+                    self.registers.write_eip(trg);
+                }
             }
-            Opcode::JMP_NZ => {
+            Opcode::BGEU => {
                 let trg: Register = ins.arg(0);
-                let src: Register = ins.arg(1);
+                let lhs: Register = ins.arg(1);
+                let rhs: Register = ins.arg(2);
 
-                // read %src
-                let data = self.registers.read(src).to_u32();
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
 
-                if data != 0 {
+                if lhs >= rhs {
                     // read %trg
                     let trg = self.registers.read(trg);
 
@@ -286,9 +1629,7 @@ impl<T> Cpu<T> {
 
                 let exit_code = self.registers.read(src).to_u32();
 
-                println!("exited with ({})", exit_code);
-
-                return false;
+                return Ok(Some(Stop::Exit(exit_code)));
             }
             Opcode::ADDI => {
                 let lhs: Register = ins.arg(0);
@@ -300,7 +1641,8 @@ impl<T> Cpu<T> {
                 let rhs = self.registers.read(rhs).to_u32();
 
                 // write %lhs + %rhs to %dst
-                self.registers.write(dst, Word::from_u32(lhs + rhs));
+                self.registers
+                    .write(dst, Word::from_u32(lhs.wrapping_add(rhs)));
             }
             Opcode::SUBI => {
                 let lhs: Register = ins.arg(0);
@@ -312,7 +1654,8 @@ impl<T> Cpu<T> {
                 let rhs = self.registers.read(rhs).to_u32();
 
                 // write %lhs - %rhs to %dst
-                self.registers.write(dst, Word::from_u32(lhs - rhs));
+                self.registers
+                    .write(dst, Word::from_u32(lhs.wrapping_sub(rhs)));
             }
             Opcode::MULI => {
                 let lhs: Register = ins.arg(0);
@@ -324,7 +1667,8 @@ impl<T> Cpu<T> {
                 let rhs = self.registers.read(rhs).to_u32();
 
                 // write %lhs * %rhs to %dst
-                self.registers.write(dst, Word::from_u32(lhs * rhs));
+                self.registers
+                    .write(dst, Word::from_u32(lhs.wrapping_mul(rhs)));
             }
             Opcode::DIVI => {
                 let lhs: Register = ins.arg(0);
@@ -336,7 +1680,21 @@ impl<T> Cpu<T> {
                 let rhs = self.registers.read(rhs).to_u32();
 
                 // write %lhs / %rhs to %dst
-                self.registers.write(dst, Word::from_u32(lhs / rhs));
+                let data = lhs.checked_div(rhs).ok_or(Fault::DivideByZero)?;
+                self.registers.write(dst, Word::from_u32(data));
+            }
+            Opcode::MODI => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
+
+                // write %lhs % %rhs to %dst
+                let data = lhs.checked_rem(rhs).ok_or(Fault::DivideByZero)?;
+                self.registers.write(dst, Word::from_u32(data));
             }
             Opcode::GTI => {
                 let lhs: Register = ins.arg(0);
@@ -414,20 +1772,275 @@ impl<T> Cpu<T> {
                 self.registers
                     .write(dst, Word::from_u32((lhs == rhs) as u32));
             }
+            Opcode::ADDC => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
+
+                // write %lhs + %rhs to %dst and the carry to ecf
+                let (data, carry) = lhs.overflowing_add(rhs);
+                self.registers.write(dst, Word::from_u32(data));
+                self.registers
+                    .write(Register::ECF, Word::from_u32(carry as u32));
+            }
+            Opcode::SUBC => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
+
+                // write %lhs - %rhs to %dst and the carry to ecf
+                let (data, carry) = lhs.overflowing_sub(rhs);
+                self.registers.write(dst, Word::from_u32(data));
+                self.registers
+                    .write(Register::ECF, Word::from_u32(carry as u32));
+            }
+            Opcode::MULC => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32();
+                let rhs = self.registers.read(rhs).to_u32();
+
+                // write %lhs * %rhs to %dst and the carry to ecf
+                let (data, carry) = lhs.overflowing_mul(rhs);
+                self.registers.write(dst, Word::from_u32(data));
+                self.registers
+                    .write(Register::ECF, Word::from_u32(carry as u32));
+            }
+            Opcode::MULH => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_i32() as i64;
+                let rhs = self.registers.read(rhs).to_i32() as i64;
+
+                // write the high word of %lhs * %rhs to %dst
+                let data = ((lhs * rhs) >> 32) as i32;
+                self.registers.write(dst, Word::from_i32(data));
+            }
+            Opcode::MULHU => {
+                let lhs: Register = ins.arg(0);
+                let rhs: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %lhs and %rhs
+                let lhs = self.registers.read(lhs).to_u32() as u64;
+                let rhs = self.registers.read(rhs).to_u32() as u64;
+
+                // write the high word of %lhs * %rhs to %dst
+                let data = ((lhs * rhs) >> 32) as u32;
+                self.registers.write(dst, Word::from_u32(data));
+            }
+
+            Opcode::SHR => {
+                let src: Register = ins.arg(0);
+                let shift: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %src and %shift, only the low 5 bits of %shift are used
+                let src = self.registers.read(src).to_u32();
+                let shift = self.registers.read(shift).to_u32() & 31;
+
+                // write %src >> %shift to %dst
+                self.registers.write(dst, Word::from_u32(src >> shift));
+            }
+            Opcode::SHL => {
+                let src: Register = ins.arg(0);
+                let shift: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %src and %shift, only the low 5 bits of %shift are used
+                let src = self.registers.read(src).to_u32();
+                let shift = self.registers.read(shift).to_u32() & 31;
+
+                // write %src << %shift to %dst
+                self.registers.write(dst, Word::from_u32(src << shift));
+            }
+            Opcode::SAR => {
+                let src: Register = ins.arg(0);
+                let shift: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %src and %shift, only the low 5 bits of %shift are used
+                let src = self.registers.read(src).to_i32();
+                let shift = self.registers.read(shift).to_u32() & 31;
+
+                // write %src >> %shift keeping the sign to %dst
+                self.registers.write(dst, Word::from_i32(src >> shift));
+            }
+            Opcode::ROL => {
+                let src: Register = ins.arg(0);
+                let shift: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %src and %shift, only the low 5 bits of %shift are used
+                let src = self.registers.read(src).to_u32();
+                let shift = self.registers.read(shift).to_u32() & 31;
+
+                // write %src rotated left by %shift to %dst
+                self.registers
+                    .write(dst, Word::from_u32(src.rotate_left(shift)));
+            }
+            Opcode::ROR => {
+                let src: Register = ins.arg(0);
+                let shift: Register = ins.arg(1);
+                let dst: Register = ins.arg(2);
+
+                // read %src and %shift, only the low 5 bits of %shift are used
+                let src = self.registers.read(src).to_u32();
+                let shift = self.registers.read(shift).to_u32() & 31;
+
+                // write %src rotated right by %shift to %dst
+                self.registers
+                    .write(dst, Word::from_u32(src.rotate_right(shift)));
+            }
+            Opcode::NOT => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+
+                // read %src
+                let src = self.registers.read(src).to_u32();
+
+                // write !%src to %dst
+                self.registers.write(dst, Word::from_u32(!src));
+            }
+            Opcode::CLZ => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+
+                // read %src
+                let src = self.registers.read(src).to_u32();
+
+                // write the leading zeros of %src to %dst
+                self.registers
+                    .write(dst, Word::from_u32(src.leading_zeros()));
+            }
+            Opcode::CTZ => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+
+                // read %src
+                let src = self.registers.read(src).to_u32();
+
+                // write the trailing zeros of %src to %dst
+                self.registers
+                    .write(dst, Word::from_u32(src.trailing_zeros()));
+            }
+            Opcode::POPCNT => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+
+                // read %src
+                let src = self.registers.read(src).to_u32();
+
+                // write the set bits of %src to %dst
+                self.registers.write(dst, Word::from_u32(src.count_ones()));
+            }
+            Opcode::BSWAP => {
+                let src: Register = ins.arg(0);
+                let dst: Register = ins.arg(1);
+
+                // read %src
+                let src = self.registers.read(src).to_u32();
+
+                // write %src with its bytes reversed to %dst
+                self.registers.write(dst, Word::from_u32(src.swap_bytes()));
+            }
+            Opcode::SETPT => {
+                let table: Register = ins.arg(0);
+                let len: Register = ins.arg(1);
+
+                // read %table and %len
+                let table = self.registers.read(table).to_u32();
+                let len = self.registers.read(len).to_u32();
+
+                // install the page table, an empty table disables translation
+                self.mmu = if len > 0 {
+                    Some(Mmu::new(self.abi.page_size, table, len))
+                } else {
+                    None
+                };
+            }
+            Opcode::TLBFLUSH => {
+                if let Some(ref mut mmu) = self.mmu {
+                    mmu.flush();
+                }
+            }
+            Opcode::SETTRAP => {
+                let handler: Register = ins.arg(0);
+
+                // write %handler to the trap handler
+                self.trap_handler = self.registers.read(handler).to_u32();
+            }
+            Opcode::TRAPINFO => {
+                let address: Register = ins.arg(0);
+                let access: Register = ins.arg(1);
+
+                let (fault_address, fault_access) = match self.trap {
+                    Some(trap) => (trap.address, trap.access as u32 + 1),
+                    None => (0, 0),
+                };
+
+                // write the faulting address and the kind of access
+                self.registers.write(address, Word::from_u32(fault_address));
+                self.registers.write(access, Word::from_u32(fault_access));
+            }
+            Opcode::USER => {
+                let trg: Register = ins.arg(0);
+
+                // read %trg
+                let trg = self.registers.read(trg);
+
+                // enter user mode at %trg
+                self.supervisor = false;
+                self.registers.write_eip(trg);
+            }
+            Opcode::TRET => {
+                // return to the trapping instruction in user mode
+                if let Some(trap) = self.trap.take() {
+                    self.supervisor = false;
+                    self.registers.write_eip(Word::from_u32(trap.eip));
+                }
+            }
+            Opcode::EI => {
+                self.interrupts_enabled = true;
+            }
+            Opcode::DI => {
+                self.interrupts_enabled = false;
+            }
+            Opcode::IRET => {
+                // return to the interrupted instruction and enable interrupts
+                if let Some(frame) = self.interrupt.take() {
+                    self.supervisor = frame.supervisor;
+                    self.registers.write_eip(Word::from_u32(frame.eip));
+                    self.registers.write_erp(Word::from_u32(frame.erp));
+                    self.interrupts_enabled = true;
+                }
+            }
             _ => {
-                panic!("invalid opcode {}", ins.opcode.0);
+                return Err(Fault::InvalidOpcode { opcode: ins.opcode });
             }
         }
 
-        true
+        Ok(None)
     }
 
-    pub fn run(&mut self, state: &mut T) {
+    pub fn run(&mut self, state: &mut T) -> Result<Stop, Fault> {
         loop {
-            let running = self.eval_instruction(state);
-
-            if !running {
-                break;
+            if let Some(stop) = self.eval_instruction(state)? {
+                break Ok(stop);
             }
         }
     }
@@ -0,0 +1,58 @@
+use crate::{Memory, Word};
+
+/// A peripheral mapped into the address space of a [`Cpu`](crate::Cpu).
+///
+/// `offset` is relative to the address the device is mapped at and `width` is
+/// the number of bytes accessed, either 1, 2 or 4.
+pub trait Device {
+    fn read(&mut self, offset: u32, width: u8) -> Word;
+
+    fn write(&mut self, offset: u32, data: Word, width: u8);
+
+    /// Called with physical memory after every write to the device, for
+    /// devices that access memory directly. Devices check permissions with
+    /// [`Memory::is_allowed`], writes through `memory` bypass them.
+    ///
+    /// Returns whether memory was written.
+    fn dma(&mut self, _memory: &mut Memory) -> bool {
+        false
+    }
+
+    /// Called before every instruction with the number of cycles executed,
+    /// returns an interrupt line to raise.
+    fn poll(&mut self, _cycles: u64) -> Option<u8> {
+        None
+    }
+
+    /// Returns a copy of the device for a [`Cpu::fork`](crate::Cpu::fork).
+    ///
+    /// Devices that return `None` are not mapped in the forked cpu.
+    fn fork(&self) -> Option<Box<dyn Device>> {
+        None
+    }
+}
+
+pub(crate) struct MappedDevice {
+    pub address: u32,
+    pub size: u32,
+    pub device: Box<dyn Device>,
+}
+
+impl MappedDevice {
+    pub fn contains(&self, ptr: u32, len: u32) -> bool {
+        let end = self.address as u64 + self.size as u64;
+        ptr >= self.address && ptr as u64 + len as u64 <= end
+    }
+
+    pub fn overlaps(&self, address: u32, size: u32) -> bool {
+        let end = self.address as u64 + self.size as u64;
+        (address as u64) < end && address as u64 + size as u64 > self.address as u64
+    }
+}
+
+/// Returns the low `width` bytes of `value`, what a register reads as with a
+/// narrower access.
+pub(crate) fn mask_to_width(value: u32, width: u8) -> u32 {
+    let bits = 8 * width.min(Word::WIDTH) as u32;
+    ((1u64 << bits) - 1) as u32 & value
+}
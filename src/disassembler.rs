@@ -0,0 +1,107 @@
+use std::fmt;
+
+use crate::{instruction::Operand, Instruction, Opcode, Program, Register, Word};
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((mnemonic, operands)) = self.opcode.syntax() else {
+            return write!(f, "invalid opcode {}", self.opcode.0);
+        };
+
+        f.write_str(mnemonic)?;
+
+        for (index, operand) in operands.iter().enumerate() {
+            match operand {
+                Operand::Reg => write!(f, " {}", self.arg::<Register>(index))?,
+                Operand::Width => write!(f, " {}", self.arg::<u8>(index))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A word of a program decoded by [`disassemble`].
+#[derive(Clone, Copy, Debug)]
+pub enum Decoded {
+    Instruction(Instruction),
+    /// A `const` instruction and the word it loads, which takes two words.
+    Constant {
+        dst: Register,
+        data: Word,
+    },
+    /// A `memcmp` instruction and its destination, which takes two words.
+    Compare {
+        ins: Instruction,
+        dst: Register,
+    },
+    /// A word that isn't an instruction, like the contents of string constants.
+    Data(Word),
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Instruction(instruction) => instruction.fmt(f),
+            Self::Constant { dst, data } => write!(f, "const {}u {}", data.to_u32(), dst),
+            Self::Compare { ins, dst } => write!(f, "{} {}", ins, dst),
+            // the assembler has no data directive, so data is a comment
+            Self::Data(word) => write!(f, "// data {:#010x}", word.to_u32()),
+        }
+    }
+}
+
+/// Decodes `program` with the offset of every instruction.
+///
+/// Labels are resolved when assembling, so they are shown as the offsets
+/// they were resolved to.
+pub fn disassemble(program: &Program) -> Vec<(u32, Decoded)> {
+    let words = program
+        .bytes()
+        .chunks(Word::SIZE as usize)
+        .map(|chunk| {
+            let mut bytes = [0; 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            Word::from_bytes(bytes)
+        })
+        .collect::<Vec<_>>();
+
+    let mut decoded = Vec::new();
+    let mut index = 0;
+
+    while index < words.len() {
+        let offset = index as u32 * Word::SIZE;
+        let instruction = Instruction::from_word(words[index]);
+
+        let line = match words.get(index + 1) {
+            Some(&data) if instruction.opcode == Opcode::CONST => {
+                index += 1;
+
+                Decoded::Constant {
+                    dst: instruction.arg(0),
+                    data,
+                }
+            }
+            Some(&data) if instruction.opcode == Opcode::MEMCMP => {
+                index += 1;
+
+                Decoded::Compare {
+                    ins: instruction,
+                    dst: Register::new(data.to_bytes()[0]),
+                }
+            }
+            // a memcmp at the end of the program is missing its destination
+            _ if instruction.opcode.syntax().is_some()
+                && instruction.opcode.size() == Word::SIZE =>
+            {
+                Decoded::Instruction(instruction)
+            }
+            _ => Decoded::Data(words[index]),
+        };
+
+        decoded.push((offset, line));
+        index += 1;
+    }
+
+    decoded
+}
@@ -0,0 +1,49 @@
+use crate::{Access, MemoryError, Opcode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    InvalidOpcode { opcode: Opcode },
+    OutOfBounds { address: u32, len: u32 },
+    InvalidWidth { width: u8 },
+    DivideByZero,
+    Protection { address: u32, access: Access },
+    PageFault { address: u32, access: Access },
+    Privileged { opcode: Opcode },
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidOpcode { opcode } => write!(f, "invalid opcode {}", opcode.0),
+            Self::OutOfBounds { address, len } => write!(
+                f,
+                "memory access of {} bytes at {:#x} is out of bounds",
+                len, address
+            ),
+            Self::InvalidWidth { width } => {
+                write!(f, "invalid width {}, expected 1, 2 or 4", width)
+            }
+            Self::DivideByZero => f.write_str("division by zero"),
+            Self::Protection { address, access } => {
+                write!(f, "{} access at {:#x} is not permitted", access, address)
+            }
+            Self::PageFault { address, access } => {
+                write!(f, "page fault on {} access at {:#x}", access, address)
+            }
+            Self::Privileged { opcode } => {
+                write!(f, "opcode {} is only allowed in supervisor mode", opcode.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+impl From<MemoryError> for Fault {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::OutOfBounds { address, len } => Self::OutOfBounds { address, len },
+            MemoryError::InvalidWidth { width } => Self::InvalidWidth { width },
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::io::{self, SeekFrom};
+
+use crate::{vfs::Stream, Access, Cpu, CpuState, Register, SysCallError, Vfs, Word};
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileError {
+    NotFound = 1,
+    PermissionDenied = 2,
+    AlreadyExists = 3,
+    InvalidHandle = 4,
+    InvalidArgument = 5,
+    /// A guest buffer is out of bounds or doesn't allow the access.
+    BadAddress = 6,
+    Io = 7,
+}
+
+impl From<io::Error> for FileError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            io::ErrorKind::InvalidInput => Self::InvalidArgument,
+            _ => Self::Io,
+        }
+    }
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("file not found"),
+            Self::PermissionDenied => f.write_str("permission denied"),
+            Self::AlreadyExists => f.write_str("file already exists"),
+            Self::InvalidHandle => f.write_str("invalid file handle"),
+            Self::InvalidArgument => f.write_str("invalid argument"),
+            Self::BadAddress => f.write_str("bad buffer address"),
+            Self::Io => f.write_str("io error"),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+/// Mode flags of [`Files::open`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OpenFlags(pub u32);
+
+impl OpenFlags {
+    pub const READ: Self = Self(1);
+    pub const WRITE: Self = Self(2);
+    /// Creates the file if it doesn't exist, requires `WRITE` or `APPEND`.
+    pub const CREATE: Self = Self(4);
+    /// Truncates the file to zero length, requires `WRITE` or `APPEND`.
+    pub const TRUNCATE: Self = Self(8);
+    /// Writes at the end of the file, implies `WRITE`.
+    pub const APPEND: Self = Self(16);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if the file is opened for writing.
+    pub const fn writes(self) -> bool {
+        self.contains(Self::WRITE) || self.contains(Self::APPEND)
+    }
+}
+
+impl std::ops::BitOr for OpenFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A table of open files, indexed by handle.
+///
+/// Files are opened in the [`Vfs`], which is empty by default so no files can
+/// be opened.
+#[derive(Default)]
+pub struct Files {
+    vfs: Vfs,
+    handles: Vec<Option<Box<dyn Stream>>>,
+}
+
+impl Files {
+    pub const OPEN: u32 = 0;
+    pub const READ: u32 = 1;
+    pub const WRITE: u32 = 2;
+    pub const SEEK: u32 = 3;
+    pub const CLOSE: u32 = 4;
+    pub const STAT: u32 = 5;
+
+    pub fn new(vfs: Vfs) -> Self {
+        Self {
+            vfs,
+            handles: Vec::new(),
+        }
+    }
+
+    pub fn vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+
+    pub fn vfs_mut(&mut self) -> &mut Vfs {
+        &mut self.vfs
+    }
+
+    /// Registers the file sys calls at `address` to `address + 5`.
+    ///
+    /// Arguments are passed in `eax`, `ebx` and `ecx`. Sys calls write their
+    /// result to `eax` and `0` to `ebx`, or `-1` to `eax` and a [`FileError`]
+    /// to `ebx` if they fail.
+    ///
+    /// Fails if the addresses don't fit in the address space.
+    pub fn register<T: AsMut<Files>>(cpu: &mut Cpu<T>, address: u32) -> Result<(), SysCallError> {
+        // in the order of the offsets
+        cpu.register_sys_calls(
+            address,
+            &[
+                sys_open::<T>,
+                sys_read::<T>,
+                sys_write::<T>,
+                sys_seek::<T>,
+                sys_close::<T>,
+                sys_stat::<T>,
+            ],
+        )
+    }
+
+    /// Opens the file at `path` in the vfs, returning its handle.
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> Result<u32, FileError> {
+        let stream = self.vfs.open(path, flags)?;
+        Ok(self.insert(stream))
+    }
+
+    fn insert(&mut self, stream: Box<dyn Stream>) -> u32 {
+        // reuse the lowest closed handle
+        match self.handles.iter().position(Option::is_none) {
+            Some(handle) => {
+                self.handles[handle] = Some(stream);
+                handle as u32
+            }
+            None => {
+                self.handles.push(Some(stream));
+                self.handles.len() as u32 - 1
+            }
+        }
+    }
+
+    fn stream(&mut self, handle: u32) -> Result<&mut Box<dyn Stream>, FileError> {
+        self.handles
+            .get_mut(handle as usize)
+            .and_then(Option::as_mut)
+            .ok_or(FileError::InvalidHandle)
+    }
+
+    pub fn read(&mut self, handle: u32, buf: &mut [u8]) -> Result<usize, FileError> {
+        Ok(self.stream(handle)?.read(buf)?)
+    }
+
+    pub fn write(&mut self, handle: u32, buf: &[u8]) -> Result<usize, FileError> {
+        Ok(self.stream(handle)?.write(buf)?)
+    }
+
+    pub fn seek(&mut self, handle: u32, position: SeekFrom) -> Result<u64, FileError> {
+        Ok(self.stream(handle)?.seek(position)?)
+    }
+
+    pub fn close(&mut self, handle: u32) -> Result<(), FileError> {
+        let mut stream = self
+            .handles
+            .get_mut(handle as usize)
+            .and_then(Option::take)
+            .ok_or(FileError::InvalidHandle)?;
+
+        Ok(stream.flush()?)
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn stat(&mut self, handle: u32) -> Result<u64, FileError> {
+        let stream = self.stream(handle)?;
+
+        let position = stream.stream_position()?;
+        let size = stream.seek(SeekFrom::End(0))?;
+        stream.seek(SeekFrom::Start(position))?;
+
+        Ok(size)
+    }
+}
+
+/// Bytes read into guest memory at a time, so large buffers don't allocate as
+/// much on the host.
+const CHUNK: u32 = 4096;
+
+fn arg(cpu: &CpuState, register: Register) -> u32 {
+    cpu.registers.read(register).to_u32()
+}
+
+fn finish(cpu: &mut CpuState, result: Result<u32, FileError>) {
+    let (value, error) = match result {
+        Ok(value) => (value, 0),
+        Err(error) => (u32::MAX, error as u32),
+    };
+
+    cpu.registers.write(Register::EAX, Word::from_u32(value));
+    cpu.registers.write(Register::EBX, Word::from_u32(error));
+}
+
+fn sys_open<T: AsMut<Files>>(cpu: &mut CpuState, state: &mut T) {
+    let path_ptr = arg(cpu, Register::EAX);
+    let path_len = arg(cpu, Register::EBX);
+    let flags = OpenFlags(arg(cpu, Register::ECX));
+
+    if !cpu.is_allowed(path_ptr, path_len, Access::Read) {
+        return finish(cpu, Err(FileError::BadAddress));
+    }
+
+    let result = match cpu.memory.read_string(path_ptr, path_len) {
+        Ok(path) => state.as_mut().open(&path, flags),
+        Err(_) => Err(FileError::BadAddress),
+    };
+
+    finish(cpu, result);
+}
+
+fn sys_read<T: AsMut<Files>>(cpu: &mut CpuState, state: &mut T) {
+    let handle = arg(cpu, Register::EAX);
+    let ptr = arg(cpu, Register::EBX);
+    let len = arg(cpu, Register::ECX);
+
+    if !cpu.is_allowed(ptr, len, Access::Write) {
+        return finish(cpu, Err(FileError::BadAddress));
+    }
+
+    let files = state.as_mut();
+    let mut buf = vec![0; CHUNK.min(len) as usize];
+    let mut total = 0;
+
+    // reads once without a length, so the handle is still checked
+    loop {
+        let chunk = CHUNK.min(len - total) as usize;
+
+        let read = match files.read(handle, &mut buf[..chunk]) {
+            Ok(read) => read,
+            // the bytes already read are in memory, report them
+            Err(_) if total > 0 => break,
+            Err(error) => return finish(cpu, Err(error)),
+        };
+
+        // in bounds, so writing can't fail
+        let _ = cpu.memory.write_bytes(ptr + total, &buf[..read]);
+        total += read as u32;
+
+        // a short read is the end of the file, or all the input there is for now
+        if read < chunk || total == len {
+            break;
+        }
+    }
+
+    finish(cpu, Ok(total));
+}
+
+fn sys_write<T: AsMut<Files>>(cpu: &mut CpuState, state: &mut T) {
+    let handle = arg(cpu, Register::EAX);
+    let ptr = arg(cpu, Register::EBX);
+    let len = arg(cpu, Register::ECX);
+
+    if !cpu.is_allowed(ptr, len, Access::Read) {
+        return finish(cpu, Err(FileError::BadAddress));
+    }
+
+    let result = match cpu.memory.read_bytes(ptr, len) {
+        Ok(buf) => state
+            .as_mut()
+            .write(handle, &buf)
+            .map(|written| written as u32),
+        Err(_) => Err(FileError::BadAddress),
+    };
+
+    finish(cpu, result);
+}
+
+fn sys_seek<T: AsMut<Files>>(cpu: &mut CpuState, state: &mut T) {
+    let handle = arg(cpu, Register::EAX);
+    let offset = arg(cpu, Register::EBX) as i32;
+    let whence = arg(cpu, Register::ECX);
+
+    let position = match whence {
+        0 => u64::try_from(offset).map(SeekFrom::Start).ok(),
+        1 => Some(SeekFrom::Current(offset as i64)),
+        2 => Some(SeekFrom::End(offset as i64)),
+        _ => None,
+    };
+
+    let result = match position {
+        Some(position) => state
+            .as_mut()
+            .seek(handle, position)
+            .and_then(|position| u32::try_from(position).map_err(|_| FileError::InvalidArgument)),
+        None => Err(FileError::InvalidArgument),
+    };
+
+    finish(cpu, result);
+}
+
+fn sys_close<T: AsMut<Files>>(cpu: &mut CpuState, state: &mut T) {
+    let handle = arg(cpu, Register::EAX);
+
+    let result = state.as_mut().close(handle).map(|()| 0);
+
+    finish(cpu, result);
+}
+
+fn sys_stat<T: AsMut<Files>>(cpu: &mut CpuState, state: &mut T) {
+    let handle = arg(cpu, Register::EAX);
+
+    let result = state
+        .as_mut()
+        .stat(handle)
+        .map(|size| size.min(u32::MAX as u64) as u32);
+
+    finish(cpu, result);
+}
@@ -0,0 +1,245 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{Device, Word};
+
+/// A framebuffer of RGBA pixels, a byte per channel, row by row from the top
+/// left.
+///
+/// Clones share the same pixels, so the host can keep a clone to capture
+/// frames while the cpu is running.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Framebuffer {
+    /// The largest number of bytes of pixels, 2048 by 2048 pixels.
+    pub const MAX_SIZE: u32 = 0x0100_0000;
+
+    /// Creates a framebuffer of `width` by `height` black pixels.
+    ///
+    /// # Panics
+    /// Panics if the size is invalid, see [`Framebuffer::try_new`].
+    pub fn new(width: u32, height: u32) -> Self {
+        match Self::try_new(width, height) {
+            Ok(framebuffer) => framebuffer,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Creates a framebuffer of `width` by `height` black pixels, fails if
+    /// there are no pixels or they take more than [`Framebuffer::MAX_SIZE`]
+    /// bytes.
+    pub fn try_new(width: u32, height: u32) -> Result<Self, FramebufferError> {
+        let len = (width as u64 * height as u64 * 4) as usize;
+
+        if len == 0 || len > Self::MAX_SIZE as usize {
+            return Err(FramebufferError { width, height });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels: Rc::new(RefCell::new(vec![0; len])),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the number of bytes of pixels.
+    pub fn size(&self) -> u32 {
+        self.width * self.height * 4
+    }
+
+    /// Returns a copy of the current pixels.
+    pub fn frame(&self) -> Frame {
+        Frame {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.borrow().clone(),
+        }
+    }
+}
+
+impl Device for Framebuffer {
+    fn read(&mut self, offset: u32, width: u8) -> Word {
+        let pixels = self.pixels.borrow();
+
+        let mut bytes = [0; 4];
+        let start = offset as usize;
+        let width = width.min(Word::WIDTH) as usize;
+        bytes[4 - width..].copy_from_slice(&pixels[start..start + width]);
+
+        Word::from_bytes(bytes)
+    }
+
+    fn write(&mut self, offset: u32, data: Word, width: u8) {
+        let mut pixels = self.pixels.borrow_mut();
+
+        let start = offset as usize;
+        let width = width.min(Word::WIDTH) as usize;
+        let bytes = data.to_bytes();
+        pixels[start..start + width].copy_from_slice(&bytes[4 - width..]);
+    }
+
+    fn fork(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(Self {
+            width: self.width,
+            height: self.height,
+            pixels: Rc::new(RefCell::new(self.pixels.borrow().clone())),
+        }))
+    }
+}
+
+/// Why a [`Framebuffer`] can't be created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FramebufferError {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "framebuffer of {}x{} pixels is empty or larger than {} bytes",
+            self.width,
+            self.height,
+            Framebuffer::MAX_SIZE
+        )
+    }
+}
+
+impl std::error::Error for FramebufferError {}
+
+/// A captured image of a [`Framebuffer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Writes the frame as a binary PPM, which has no alpha channel.
+    pub fn write_ppm(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+
+        for pixel in self.pixels.chunks_exact(4) {
+            writer.write_all(&pixel[..3])?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the frame as an uncompressed PNG.
+    pub fn write_png(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits per channel, RGBA, no interlacing
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(writer, b"IHDR", &header)?;
+
+        // every row starts with filter type 0, no filtering
+        let row = self.width as usize * 4;
+        let mut data = Vec::with_capacity((row + 1) * self.height as usize);
+        for row in self.pixels.chunks(row.max(1)) {
+            data.push(0);
+            data.extend_from_slice(row);
+        }
+
+        write_chunk(writer, b"IDAT", &zlib_stored(&data))?;
+        write_chunk(writer, b"IEND", &[])
+    }
+
+    /// Saves the frame to `path`, as a PPM if the extension is `ppm` and as a
+    /// PNG otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match path.extension() {
+            Some(extension) if extension == "ppm" => self.write_ppm(&mut writer)?,
+            _ => self.write_png(&mut writer)?,
+        }
+
+        writer.flush()
+    }
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps `data` in a zlib stream of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
@@ -0,0 +1,121 @@
+use crate::{Cpu, CpuState, Memory, Permissions, Register, SysCallError, Word};
+
+/// The heap of a guest, grown and shrunk by moving its break.
+///
+/// The heap is the memory from `start` to the break, memory is grown when the
+/// break moves past its end.
+///
+/// The heap is host state, [`Cpu::step_back`] and [`Cpu::restore`] undo the
+/// growth of memory but not the break. Hosts that undo instructions or restore
+/// snapshots keep a copy of the heap with them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Heap {
+    start: u32,
+    brk: u32,
+    limit: u32,
+}
+
+impl Heap {
+    pub const BRK: u32 = 0;
+    pub const SBRK: u32 = 1;
+
+    /// Creates an empty heap at `start` that can grow up to `limit`.
+    pub fn new(start: u32, limit: u32) -> Self {
+        Self {
+            start,
+            brk: start,
+            limit,
+        }
+    }
+
+    /// Creates the heap of a cpu with a loaded program, starting
+    /// [`Abi::stack_size`](crate::Abi::stack_size) bytes after the stack and
+    /// limited to below device memory.
+    pub fn after_stack<T>(cpu: &Cpu<T>) -> Self {
+        let abi = cpu.abi();
+        let esp = cpu.registers().esp().to_u32();
+
+        let start = esp
+            .saturating_add(abi.stack_size)
+            .div_ceil(Word::SIZE)
+            .saturating_mul(Word::SIZE);
+
+        Self::new(start, abi.device_memory)
+    }
+
+    /// Registers the heap sys calls at `address` and `address + 1`.
+    ///
+    /// `brk` sets the break to `eax` unless it is `0`, writing the break to
+    /// `eax` and the start of the heap to `ebx`. The break is unchanged if it
+    /// can't be moved. `sbrk` moves the break by the signed `eax`, writing the
+    /// previous break to `eax`, or `-1` if it can't be moved.
+    ///
+    /// Fails if the addresses don't fit in the address space.
+    pub fn register<T: AsMut<Heap>>(cpu: &mut Cpu<T>, address: u32) -> Result<(), SysCallError> {
+        cpu.register_sys_calls(address, &[sys_brk::<T>, sys_sbrk::<T>])
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn brk(&self) -> u32 {
+        self.brk
+    }
+
+    /// Moves the break to `brk`, growing `memory` if needed. Returns false if
+    /// `brk` is before the start of the heap or past its limit.
+    pub fn set_brk(&mut self, brk: u32, memory: &mut Memory) -> bool {
+        if brk < self.start || brk > self.limit {
+            return false;
+        }
+
+        let size = memory.size();
+
+        if brk as u64 > size {
+            memory.grow(brk as u64 - size);
+            memory.protect(
+                size as u32,
+                (brk as u64 - size) as u32,
+                Permissions::READ | Permissions::WRITE,
+            );
+        }
+
+        self.brk = brk;
+
+        true
+    }
+
+    /// Moves the break by `increment`, returning the previous break.
+    pub fn sbrk(&mut self, increment: i32, memory: &mut Memory) -> Option<u32> {
+        let previous = self.brk;
+        let brk = previous.checked_add_signed(increment)?;
+
+        self.set_brk(brk, memory).then_some(previous)
+    }
+}
+
+fn sys_brk<T: AsMut<Heap>>(cpu: &mut CpuState, state: &mut T) {
+    let heap = state.as_mut();
+    let brk = cpu.registers.read(Register::EAX).to_u32();
+
+    if brk != 0 {
+        heap.set_brk(brk, cpu.memory);
+    }
+
+    cpu.registers
+        .write(Register::EAX, Word::from_u32(heap.brk()));
+    cpu.registers
+        .write(Register::EBX, Word::from_u32(heap.start()));
+}
+
+fn sys_sbrk<T: AsMut<Heap>>(cpu: &mut CpuState, state: &mut T) {
+    let increment = cpu.registers.read(Register::EAX).to_u32() as i32;
+
+    let previous = state
+        .as_mut()
+        .sbrk(increment, cpu.memory)
+        .unwrap_or(u32::MAX);
+
+    cpu.registers.write(Register::EAX, Word::from_u32(previous));
+}
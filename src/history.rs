@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+
+use crate::{InterruptFrame, Memory, Registers, Trap};
+
+/// The state needed to undo a single instruction.
+pub(crate) struct Step {
+    pub(crate) registers: Registers,
+    pub(crate) cycles: u64,
+    pub(crate) supervisor: bool,
+    pub(crate) page_table: Option<(u32, u32)>,
+    pub(crate) trap_handler: u32,
+    pub(crate) trap: Option<Trap>,
+    pub(crate) interrupts: u32,
+    pub(crate) interrupts_enabled: bool,
+    pub(crate) interrupt: Option<InterruptFrame>,
+    /// Previous contents of every range of memory written, in order.
+    pub(crate) writes: Vec<(u32, Vec<u8>)>,
+    /// The whole memory for sys calls, which may write anywhere.
+    pub(crate) memory: Option<Memory>,
+}
+
+impl Step {
+    /// Returns true if the step changed the byte at `address`, `memory` is the
+    /// memory after the step.
+    pub(crate) fn writes_to(&self, address: u32, memory: &Memory) -> bool {
+        let wrote = self.writes.iter().any(|(ptr, bytes)| {
+            let offset = address.wrapping_sub(*ptr);
+            (offset as usize) < bytes.len()
+        });
+
+        let changed = self
+            .memory
+            .as_ref()
+            .is_some_and(|old| old.read(address, 1) != memory.read(address, 1));
+
+        wrote || changed
+    }
+}
+
+/// A bounded log of executed instructions used to step backwards.
+#[derive(Default)]
+pub(crate) struct History {
+    limit: usize,
+    steps: VecDeque<Step>,
+    /// The step of the instruction being executed.
+    pub(crate) current: Option<Step>,
+}
+
+impl History {
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+
+        while self.steps.len() > limit {
+            self.steps.pop_front();
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.steps.clear();
+        self.current = None;
+    }
+
+    /// Records a write of `len` bytes at `ptr` to `memory` by the current step.
+    pub(crate) fn record_write(&mut self, memory: &Memory, ptr: u32, len: u32) {
+        if let Some(ref mut step) = self.current {
+            if step.memory.is_none() {
+                if let Ok(bytes) = memory.read_bytes(ptr, len) {
+                    step.writes.push((ptr, bytes.into_owned()));
+                }
+            }
+        }
+    }
+
+    /// Returns whether the current step would record all of memory.
+    pub(crate) fn is_recording(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|step| step.memory.is_none())
+    }
+
+    /// Records all of `memory` for the current step, for writes that can't be
+    /// recorded individually.
+    pub(crate) fn record_memory(&mut self, memory: &Memory) {
+        if let Some(ref mut step) = self.current {
+            if step.memory.is_none() {
+                step.memory = Some(memory.clone());
+            }
+        }
+    }
+
+    /// Moves the current step into the log, dropping the oldest step if full.
+    pub(crate) fn finish(&mut self) {
+        if let Some(step) = self.current.take() {
+            if self.steps.len() == self.limit {
+                self.steps.pop_front();
+            }
+
+            self.steps.push_back(step);
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Step> {
+        self.steps.pop_back()
+    }
+}
@@ -14,6 +14,7 @@ impl Register {
     pub const ERP: Self = Self::new(6);
     pub const EBP: Self = Self::new(7);
     pub const EXP: Self = Self::new(8);
+    pub const ECF: Self = Self::new(9);
 
     pub const MIN_REGISTERS: usize = 12;
 
@@ -22,6 +23,19 @@ impl Register {
     }
 }
 
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const NAMES: [&str; 10] = [
+            "eax", "ebx", "ecx", "edx", "eip", "esp", "erp", "ebp", "exp", "ecf",
+        ];
+
+        match NAMES.get(self.0 as usize) {
+            Some(name) => f.write_str(name),
+            None => write!(f, "%{}", self.0),
+        }
+    }
+}
+
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Opcode(pub u8);
@@ -33,12 +47,23 @@ impl Opcode {
     pub const POP: Self = Self(3);
     pub const LOAD: Self = Self(4);
     pub const STORE: Self = Self(5);
+    pub const LOADS: Self = Self(6);
+    pub const MEMCPY: Self = Self(7);
+    pub const MEMSET: Self = Self(8);
+    pub const MEMCMP: Self = Self(9);
 
     pub const JMP: Self = Self(16);
     pub const JMP_NZ: Self = Self(17);
     pub const CALL: Self = Self(18);
     pub const RET: Self = Self(19);
     pub const EXIT: Self = Self(20);
+    pub const JMP_Z: Self = Self(21);
+    pub const BEQ: Self = Self(22);
+    pub const BNE: Self = Self(23);
+    pub const BLT: Self = Self(24);
+    pub const BGE: Self = Self(25);
+    pub const BLTU: Self = Self(26);
+    pub const BGEU: Self = Self(27);
 
     pub const ADDI: Self = Self(32);
     pub const SUBI: Self = Self(33);
@@ -47,12 +72,28 @@ impl Opcode {
     pub const MODI: Self = Self(36);
     pub const GTI: Self = Self(37);
     pub const LTI: Self = Self(38);
-
-    pub const SHIFT: Self = Self(48);
+    pub const ADDC: Self = Self(39);
+    pub const SUBC: Self = Self(40);
+    pub const MULC: Self = Self(41);
+    pub const MULH: Self = Self(42);
+    pub const MULHU: Self = Self(43);
+
+    pub const SHR: Self = Self(48);
+    #[deprecated(note = "renamed to `SHR`")]
+    pub const SHIFT: Self = Self::SHR;
     pub const AND: Self = Self(49);
     pub const OR: Self = Self(50);
     pub const XOR: Self = Self(51);
     pub const EQ: Self = Self(52);
+    pub const SHL: Self = Self(53);
+    pub const SAR: Self = Self(54);
+    pub const ROL: Self = Self(55);
+    pub const ROR: Self = Self(56);
+    pub const NOT: Self = Self(57);
+    pub const CLZ: Self = Self(58);
+    pub const CTZ: Self = Self(59);
+    pub const POPCNT: Self = Self(60);
+    pub const BSWAP: Self = Self(61);
 
     pub const ADDF: Self = Self(64);
     pub const SUBF: Self = Self(65);
@@ -60,6 +101,129 @@ impl Opcode {
     pub const DIVF: Self = Self(67);
     pub const MODF: Self = Self(68);
     pub const FLOORF: Self = Self(72);
+
+    pub const SETPT: Self = Self(80);
+    pub const TLBFLUSH: Self = Self(81);
+    pub const SETTRAP: Self = Self(82);
+    pub const TRAPINFO: Self = Self(83);
+    pub const USER: Self = Self(84);
+    pub const TRET: Self = Self(85);
+    pub const EI: Self = Self(86);
+    pub const DI: Self = Self(87);
+    pub const IRET: Self = Self(88);
+
+    /// Returns true if the opcode may only be executed in supervisor mode.
+    pub const fn is_privileged(self) -> bool {
+        matches!(self.0, 80..=88)
+    }
+
+    /// Returns the number of bytes the instruction takes, `const` and
+    /// `memcmp` are followed by a word of data.
+    pub const fn size(self) -> u32 {
+        match self.0 {
+            0 | 9 => Word::SIZE * 2,
+            _ => Word::SIZE,
+        }
+    }
+}
+
+/// An operand of an instruction in assembly.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Operand {
+    Reg,
+    Width,
+}
+
+const R: &[Operand] = &[Operand::Reg];
+const RR: &[Operand] = &[Operand::Reg, Operand::Reg];
+const RRR: &[Operand] = &[Operand::Reg, Operand::Reg, Operand::Reg];
+const RRW: &[Operand] = &[Operand::Reg, Operand::Reg, Operand::Width];
+
+/// The mnemonic and operands of every opcode except `const`, shared by the
+/// assembler and the disassembler. `memcmp` is also followed by its
+/// destination.
+const SYNTAX: &[(Opcode, &str, &[Operand])] = &[
+    (Opcode::MOV, "mov", RR),
+    (Opcode::PUSH, "push", R),
+    (Opcode::POP, "pop", R),
+    (Opcode::LOAD, "load", RRW),
+    (Opcode::STORE, "store", RRW),
+    (Opcode::LOADS, "loads", RRW),
+    (Opcode::MEMCPY, "memcpy", RRR),
+    (Opcode::MEMSET, "memset", RRR),
+    (Opcode::MEMCMP, "memcmp", RRR),
+    (Opcode::JMP, "jmp", R),
+    (Opcode::JMP_NZ, "jmpnz", RR),
+    (Opcode::CALL, "call", R),
+    (Opcode::RET, "ret", &[]),
+    (Opcode::EXIT, "exit", R),
+    (Opcode::JMP_Z, "jmpz", RR),
+    (Opcode::BEQ, "beq", RRR),
+    (Opcode::BNE, "bne", RRR),
+    (Opcode::BLT, "blt", RRR),
+    (Opcode::BGE, "bge", RRR),
+    (Opcode::BLTU, "bltu", RRR),
+    (Opcode::BGEU, "bgeu", RRR),
+    (Opcode::ADDI, "addi", RRR),
+    (Opcode::SUBI, "subi", RRR),
+    (Opcode::MULI, "muli", RRR),
+    (Opcode::DIVI, "divi", RRR),
+    (Opcode::MODI, "modi", RRR),
+    (Opcode::GTI, "gti", RRR),
+    (Opcode::LTI, "lti", RRR),
+    (Opcode::ADDC, "addc", RRR),
+    (Opcode::SUBC, "subc", RRR),
+    (Opcode::MULC, "mulc", RRR),
+    (Opcode::MULH, "mulh", RRR),
+    (Opcode::MULHU, "mulhu", RRR),
+    (Opcode::SHR, "shr", RRR),
+    (Opcode::AND, "and", RRR),
+    (Opcode::OR, "or", RRR),
+    (Opcode::XOR, "xor", RRR),
+    (Opcode::EQ, "eq", RRR),
+    (Opcode::SHL, "shl", RRR),
+    (Opcode::SAR, "sar", RRR),
+    (Opcode::ROL, "rol", RRR),
+    (Opcode::ROR, "ror", RRR),
+    (Opcode::NOT, "not", RR),
+    (Opcode::CLZ, "clz", RR),
+    (Opcode::CTZ, "ctz", RR),
+    (Opcode::POPCNT, "popcnt", RR),
+    (Opcode::BSWAP, "bswap", RR),
+    (Opcode::ADDF, "addf", RRR),
+    (Opcode::SUBF, "subf", RRR),
+    (Opcode::MULF, "mulf", RRR),
+    (Opcode::DIVF, "divf", RRR),
+    (Opcode::MODF, "modf", RRR),
+    (Opcode::FLOORF, "floorf", RR),
+    (Opcode::SETPT, "setpt", RR),
+    (Opcode::TLBFLUSH, "tlbflush", &[]),
+    (Opcode::SETTRAP, "settrap", R),
+    (Opcode::TRAPINFO, "trapinfo", RR),
+    (Opcode::USER, "user", R),
+    (Opcode::TRET, "tret", &[]),
+    (Opcode::EI, "ei", &[]),
+    (Opcode::DI, "di", &[]),
+    (Opcode::IRET, "iret", &[]),
+];
+
+impl Opcode {
+    /// Returns the mnemonic and operands of the opcode, `None` for `const` and
+    /// invalid opcodes.
+    pub(crate) fn syntax(self) -> Option<(&'static str, &'static [Operand])> {
+        SYNTAX
+            .iter()
+            .find(|(opcode, ..)| *opcode == self)
+            .map(|&(_, mnemonic, operands)| (mnemonic, operands))
+    }
+
+    /// Returns the opcode with `mnemonic` and its operands.
+    pub(crate) fn from_mnemonic(mnemonic: &str) -> Option<(Self, &'static [Operand])> {
+        SYNTAX
+            .iter()
+            .find(|(_, other, _)| *other == mnemonic)
+            .map(|&(opcode, _, operands)| (opcode, operands))
+    }
 }
 
 #[repr(transparent)]
@@ -140,7 +304,7 @@ pub struct Args {
 impl Args {
     pub fn from_bytes(args: [u8; 3]) -> Self {
         Self {
-            inner: unsafe { mem::transmute(args) },
+            inner: unsafe { mem::transmute::<[u8; 3], [Arg; 3]>(args) },
         }
     }
 
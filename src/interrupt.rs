@@ -0,0 +1,49 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+/// Pending interrupt lines of a [`Cpu`](crate::Cpu).
+///
+/// Clones share the same lines, so devices and host threads can keep a handle
+/// to raise interrupts while the cpu is running.
+#[derive(Clone, Debug, Default)]
+pub struct InterruptLines {
+    pending: Arc<AtomicU32>,
+}
+
+impl InterruptLines {
+    pub const COUNT: u8 = 32;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks `line` as pending, it stays pending until it is delivered.
+    pub fn raise(&self, line: u8) {
+        assert!(line < Self::COUNT, "invalid interrupt line {}", line);
+
+        self.pending.fetch_or(1 << line, Ordering::AcqRel);
+    }
+
+    /// Returns a mask of the pending lines, bit `n` is set if line `n` is pending.
+    pub fn pending(&self) -> u32 {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_pending(&self, pending: u32) {
+        self.pending.store(pending, Ordering::Release);
+    }
+
+    pub(crate) fn clear(&self, line: u8) {
+        self.pending.fetch_and(!(1 << line), Ordering::AcqRel);
+    }
+}
+
+/// The state saved when an interrupt is delivered and restored by `iret`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct InterruptFrame {
+    pub(crate) eip: u32,
+    pub(crate) erp: u32,
+    pub(crate) supervisor: bool,
+}
@@ -1,13 +1,44 @@
 mod assembler;
+mod block;
+mod config;
 mod cpu;
+mod device;
+mod disassembler;
+mod fault;
+mod files;
+mod framebuffer;
+mod heap;
+mod history;
 mod instruction;
+mod interrupt;
 mod label;
 mod memory;
+mod mmu;
 mod program;
+mod snapshot;
+mod timer;
+mod uart;
+mod vfs;
+mod watch;
 
 pub use assembler::*;
+pub use block::*;
+pub use config::*;
 pub use cpu::*;
+pub use device::*;
+pub use disassembler::*;
+pub use fault::*;
+pub use files::*;
+pub use framebuffer::*;
+pub use heap::*;
 pub use instruction::*;
+pub use interrupt::*;
 pub use label::*;
 pub use memory::*;
+pub use mmu::*;
 pub use program::*;
+pub use snapshot::*;
+pub use timer::*;
+pub use uart::*;
+pub use vfs::*;
+pub use watch::*;
@@ -1,161 +1,427 @@
 use std::{
-    alloc::{self, Layout},
     borrow::Cow,
-    ptr::{self, NonNull},
+    collections::HashMap,
+    io::{self, Read, Write},
+    ops::BitOr,
+    sync::Arc,
 };
 
-use crate::Word;
+use crate::{
+    snapshot::{invalid_data, read_u32, read_u64, read_u8, write_u32, write_u64, write_u8},
+    Word,
+};
+
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Permissions(pub u8);
+
+impl Permissions {
+    pub const NONE: Self = Self(0);
+    pub const READ: Self = Self(1);
+    pub const WRITE: Self = Self(2);
+    pub const EXECUTE: Self = Self(4);
+    pub const ALL: Self = Self(7);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Permissions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    pub const fn permissions(self) -> Permissions {
+        match self {
+            Self::Read => Permissions::READ,
+            Self::Write => Permissions::WRITE,
+            Self::Execute => Permissions::EXECUTE,
+        }
+    }
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => f.write_str("read"),
+            Self::Write => f.write_str("write"),
+            Self::Execute => f.write_str("execute"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    OutOfBounds { address: u32, len: u32 },
+    InvalidWidth { width: u8 },
+}
+
+impl std::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfBounds { address, len } => write!(
+                f,
+                "memory access of {} bytes at {:#x} is out of bounds",
+                len, address
+            ),
+            Self::InvalidWidth { width } => {
+                write!(f, "invalid width {}, expected 1, 2 or 4", width)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    start: u64,
+    end: u64,
+    permissions: Permissions,
+}
+
+type Page = [u8; Memory::PAGE_SIZE as usize];
 
+/// Sparse guest memory.
+///
+/// Memory is split into pages that are only allocated when first written,
+/// unallocated pages read as zeros. Cloning memory shares its pages, which are
+/// copied when either clone first writes to them.
+#[derive(Clone)]
 pub struct Memory {
-    size: usize,
-    base: NonNull<u8>,
+    size: u64,
+    pages: HashMap<u32, Arc<Page>>,
+    /// Sorted, non overlapping regions, addresses outside them allow everything.
+    regions: Vec<Region>,
 }
 
 impl Memory {
+    pub const PAGE_SIZE: u32 = 4096;
+
     pub fn new() -> Self {
         Self {
             size: 0,
-            base: NonNull::dangling(),
+            pages: HashMap::new(),
+            regions: Vec::new(),
         }
     }
 
-    pub fn with_size(size: usize) -> Self {
+    pub fn with_size(size: u64) -> Self {
         let mut memory = Self::new();
         memory.grow(size);
 
         memory
     }
 
-    pub fn grow(&mut self, size: usize) {
-        let new_size = self.size + size;
+    /// Grows memory by `size` bytes, the address space is limited to 4 GiB.
+    pub fn grow(&mut self, size: u64) {
+        self.size = (self.size + size).min(u32::MAX as u64 + 1);
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the number of bytes of memory that are allocated.
+    pub fn resident_size(&self) -> u64 {
+        self.pages.len() as u64 * Self::PAGE_SIZE as u64
+    }
 
-        let new_layout = Layout::from_size_align(new_size, 1).unwrap();
+    /// Returns true if all of the `len` bytes at `ptr` are in memory.
+    pub fn in_bounds(&self, ptr: u32, len: u32) -> bool {
+        ptr as u64 + len as u64 <= self.size
+    }
 
-        let ptr = if self.size == 0 {
-            unsafe { alloc::alloc_zeroed(new_layout) }
+    fn check_bounds(&self, ptr: u32, len: u32) -> Result<(), MemoryError> {
+        if self.in_bounds(ptr, len) {
+            Ok(())
         } else {
-            let old_layout = Layout::from_size_align(self.size, 1).unwrap();
+            Err(MemoryError::OutOfBounds { address: ptr, len })
+        }
+    }
+
+    fn check_width(width: u8) -> Result<(), MemoryError> {
+        match width {
+            1 | 2 | 4 => Ok(()),
+            _ => Err(MemoryError::InvalidWidth { width }),
+        }
+    }
+
+    /// Sets the permissions of the `len` bytes starting at `ptr`, replacing
+    /// the permissions of any region they overlap.
+    pub fn protect(&mut self, ptr: u32, len: u32, permissions: Permissions) {
+        self.protect_range(ptr as u64, ptr as u64 + len as u64, permissions);
+    }
 
-            let ptr = unsafe { alloc::alloc_zeroed(new_layout) };
+    /// Sets the permissions of the bytes from `start` to `end`, which may be
+    /// the end of the 4 GiB address space.
+    fn protect_range(&mut self, start: u64, end: u64, permissions: Permissions) {
+        if start == end {
+            return;
+        }
 
-            unsafe { ptr::copy_nonoverlapping(self.base.as_ptr(), ptr, self.size) };
+        let mut regions = Vec::with_capacity(self.regions.len() + 2);
 
-            unsafe { alloc::dealloc(self.base.as_ptr(), old_layout) };
+        for region in self.regions.drain(..) {
+            if region.end <= start || region.start >= end {
+                regions.push(region);
+                continue;
+            }
 
-            ptr
-        };
+            // keep the parts of the region outside the new one
+            if region.start < start {
+                regions.push(Region {
+                    end: start,
+                    ..region
+                });
+            }
 
-        let new_base = match NonNull::new(ptr) {
-            Some(base) => base,
-            None => alloc::handle_alloc_error(new_layout),
-        };
+            if region.end > end {
+                regions.push(Region {
+                    start: end,
+                    ..region
+                });
+            }
+        }
 
-        self.size = new_size;
-        self.base = new_base;
+        regions.push(Region {
+            start,
+            end,
+            permissions,
+        });
+        regions.sort_by_key(|region| region.start);
+
+        self.regions = regions;
     }
 
-    pub fn size(&self) -> usize {
-        self.size
+    pub fn permissions(&self, ptr: u32) -> Permissions {
+        let ptr = ptr as u64;
+
+        self.regions
+            .iter()
+            .find(|region| region.start <= ptr && ptr < region.end)
+            .map_or(Permissions::ALL, |region| region.permissions)
     }
 
-    pub fn read(&self, ptr: u32, size: u8) -> Option<Word> {
-        if ptr as usize + size as usize >= self.size {
-            return None;
-        }
+    /// Returns true if every byte of the `len` bytes starting at `ptr` allows `access`.
+    pub fn is_allowed(&self, ptr: u32, len: u32, access: Access) -> bool {
+        let required = access.permissions();
 
-        let bytes = match size {
-            1 => {
-                let read = unsafe { *self.base.as_ptr().add(ptr as usize) };
-                [0, 0, 0, read]
+        let start = ptr as u64;
+        let end = start + len as u64;
+
+        for region in &self.regions {
+            if region.end <= start {
+                continue;
             }
-            2 => {
-                let read = unsafe { *self.base.as_ptr().add(ptr as usize).cast::<[u8; 2]>() };
-                [0, 0, read[0], read[1]]
+
+            if region.start >= end {
+                break;
             }
-            4 => unsafe { *self.base.as_ptr().add(ptr as usize).cast::<[u8; 4]>() },
-            _ => return None,
-        };
 
-        Some(Word::from_bytes(bytes))
+            if !region.permissions.contains(required) {
+                return false;
+            }
+        }
+
+        true
     }
 
-    pub fn read_ptr(&self, ptr: u32) -> Option<*mut u8> {
-        if ptr as usize >= self.size {
-            return None;
+    /// Calls `f` with every page overlapping the `len` bytes at `ptr`, the
+    /// offset into the page and the length of the overlap.
+    fn for_each_page(ptr: u32, len: u32, mut f: impl FnMut(u32, usize, usize)) {
+        let mut ptr = ptr as u64;
+        let end = ptr + len as u64;
+
+        while ptr < end {
+            let page = (ptr / Self::PAGE_SIZE as u64) as u32;
+            let offset = (ptr % Self::PAGE_SIZE as u64) as usize;
+            let len = (Self::PAGE_SIZE as usize - offset).min((end - ptr) as usize);
+
+            f(page, offset, len);
+
+            ptr += len as u64;
         }
+    }
+
+    fn copy_out(&self, ptr: u32, buf: &mut [u8]) {
+        let mut index = 0;
 
-        Some(unsafe { self.base.as_ptr().add(ptr as usize) })
+        Self::for_each_page(ptr, buf.len() as u32, |page, offset, len| {
+            let dst = &mut buf[index..index + len];
+
+            match self.pages.get(&page) {
+                Some(page) => dst.copy_from_slice(&page[offset..offset + len]),
+                None => dst.fill(0),
+            }
+
+            index += len;
+        });
     }
 
-    pub fn read_bytes(&self, ptr: u32, len: u32) -> Option<&[u8]> {
-        if ptr as usize + len as usize >= self.size {
-            return None;
-        }
+    fn copy_in(&mut self, ptr: u32, bytes: &[u8]) {
+        let mut index = 0;
+
+        Self::for_each_page(ptr, bytes.len() as u32, |page, offset, len| {
+            let src = &bytes[index..index + len];
+            index += len;
+
+            // writing zeros to an unallocated page doesn't change it
+            if !self.pages.contains_key(&page) && src.iter().all(|&byte| byte == 0) {
+                return;
+            }
 
-        Some(unsafe {
-            &*ptr::slice_from_raw_parts(self.base.as_ptr().add(ptr as usize), len as usize)
-        })
+            let page = self
+                .pages
+                .entry(page)
+                .or_insert_with(|| Arc::new([0; Self::PAGE_SIZE as usize]));
+
+            // copies the page if it is shared with a clone
+            Arc::make_mut(page)[offset..offset + len].copy_from_slice(src);
+        });
+    }
+
+    pub fn read(&self, ptr: u32, width: u8) -> Result<Word, MemoryError> {
+        Self::check_width(width)?;
+        self.check_bounds(ptr, width as u32)?;
+
+        let mut bytes = [0; 4];
+        self.copy_out(ptr, &mut bytes[4 - width as usize..]);
+
+        Ok(Word::from_bytes(bytes))
     }
 
-    pub fn read_string(&self, ptr: u32, len: u32) -> Option<Cow<'_, str>> {
-        if ptr as usize + len as usize >= self.size() {
-            return None;
+    pub fn read_bytes(&self, ptr: u32, len: u32) -> Result<Cow<'_, [u8]>, MemoryError> {
+        self.check_bounds(ptr, len)?;
+
+        let page = ptr / Self::PAGE_SIZE;
+        let offset = (ptr % Self::PAGE_SIZE) as usize;
+
+        // borrow the bytes if they are on a single allocated page
+        if offset + len as usize <= Self::PAGE_SIZE as usize {
+            if let Some(page) = self.pages.get(&page) {
+                return Ok(Cow::Borrowed(&page[offset..offset + len as usize]));
+            }
         }
 
-        let ptr = self.read_ptr(ptr)?;
+        let mut bytes = vec![0; len as usize];
+        self.copy_out(ptr, &mut bytes);
 
-        let utf8 = unsafe { &*ptr::slice_from_raw_parts(ptr, len as usize) };
-        let string = String::from_utf8_lossy(utf8);
+        Ok(Cow::Owned(bytes))
+    }
+
+    pub fn read_string(&self, ptr: u32, len: u32) -> Result<Cow<'_, str>, MemoryError> {
+        let string = match self.read_bytes(ptr, len)? {
+            Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+            Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+        };
 
-        Some(string)
+        Ok(string)
     }
 
-    pub fn write(&mut self, word: Word, ptr: u32, size: u8) {
-        if ptr as usize + size as usize >= self.size {
-            return;
-        }
+    pub fn write(&mut self, word: Word, ptr: u32, width: u8) -> Result<(), MemoryError> {
+        Self::check_width(width)?;
+        self.check_bounds(ptr, width as u32)?;
 
         let bytes = word.to_bytes();
+        self.copy_in(ptr, &bytes[4 - width as usize..]);
 
-        match size {
-            1 => {
-                unsafe { *self.base.as_ptr().add(ptr as usize) = bytes[3] };
-            }
-            2 => {
-                unsafe { *self.base.as_ptr().add(ptr as usize).cast() = [bytes[2], bytes[3]] };
-            }
-            4 => {
-                unsafe { *self.base.as_ptr().add(ptr as usize).cast() = bytes };
-            }
-            _ => {}
-        }
+        Ok(())
     }
 
-    pub fn write_bytes(&mut self, ptr: u32, bytes: &[u8]) {
-        if ptr as usize + bytes.len() >= self.size {
-            return;
-        }
+    pub fn write_bytes(&mut self, ptr: u32, bytes: &[u8]) -> Result<(), MemoryError> {
+        let len = u32::try_from(bytes.len()).map_err(|_| MemoryError::OutOfBounds {
+            address: ptr,
+            len: u32::MAX,
+        })?;
+        self.check_bounds(ptr, len)?;
 
-        unsafe {
-            ptr::copy(
-                bytes.as_ptr(),
-                self.base.as_ptr().add(ptr as usize),
-                bytes.len(),
-            )
-        };
+        self.copy_in(ptr, bytes);
+
+        Ok(())
     }
 
-    pub fn write_string(&mut self, ptr: u32, string: &str) {
-        self.write_bytes(ptr, string.as_bytes());
+    pub fn write_string(&mut self, ptr: u32, string: &str) -> Result<(), MemoryError> {
+        self.write_bytes(ptr, string.as_bytes())
     }
 }
 
-impl Drop for Memory {
-    fn drop(&mut self) {
-        if self.size > 0 {
-            let layout = Layout::from_size_align(self.size, 1).unwrap();
+impl Memory {
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_u64(writer, self.size)?;
+
+        write_u32(writer, self.regions.len() as u32)?;
+        for region in &self.regions {
+            write_u64(writer, region.start)?;
+            write_u64(writer, region.end)?;
+            write_u8(writer, region.permissions.0)?;
+        }
+
+        // write pages in order so equal memories serialize equally
+        let mut pages = self.pages.iter().collect::<Vec<_>>();
+        pages.sort_by_key(|&(&index, _)| index);
+
+        write_u32(writer, pages.len() as u32)?;
+        for (&index, page) in pages {
+            write_u32(writer, index)?;
+            writer.write_all(&page[..])?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let size = read_u64(reader)?;
+
+        if size > u32::MAX as u64 + 1 {
+            return Err(invalid_data("memory is larger than 4 GiB"));
+        }
+
+        let mut memory = Self::with_size(size);
+
+        for _ in 0..read_u32(reader)? {
+            let start = read_u64(reader)?;
+            let end = read_u64(reader)?;
+            let permissions = Permissions(read_u8(reader)?);
+
+            if start >= end || end > memory.size {
+                return Err(invalid_data("invalid memory region"));
+            }
 
-            unsafe { alloc::dealloc(self.base.as_ptr(), layout) };
+            memory.protect_range(start, end, permissions);
         }
+
+        for _ in 0..read_u32(reader)? {
+            let index = read_u32(reader)?;
+
+            let mut page = [0; Self::PAGE_SIZE as usize];
+            reader.read_exact(&mut page)?;
+
+            if index as u64 * Self::PAGE_SIZE as u64 >= memory.size {
+                return Err(invalid_data("memory page out of bounds"));
+            }
+
+            memory.pages.insert(index, Arc::new(page));
+        }
+
+        Ok(memory)
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
     }
 }
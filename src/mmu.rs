@@ -0,0 +1,120 @@
+use crate::{Access, Fault, Memory, Permissions, Word};
+
+/// An entry of a page table.
+///
+/// The high bits hold the page aligned physical address of the frame, the low
+/// bits hold the [`Permissions`] of the page and whether it is present.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PageTableEntry(pub u32);
+
+impl PageTableEntry {
+    pub const PRESENT: u32 = 8;
+    pub const FLAGS: u32 = 15;
+
+    pub fn new(frame: u32, permissions: Permissions) -> Self {
+        Self(frame & !Self::FLAGS | permissions.0 as u32 | Self::PRESENT)
+    }
+
+    pub fn is_present(self) -> bool {
+        self.0 & Self::PRESENT != 0
+    }
+
+    pub fn permissions(self) -> Permissions {
+        Permissions(self.0 as u8 & Permissions::ALL.0)
+    }
+
+    pub fn frame(self, page_size: u32) -> u32 {
+        self.0 & !(page_size - 1)
+    }
+}
+
+const TLB_SIZE: usize = 64;
+
+/// Translates virtual addresses of user mode to physical addresses through a
+/// single level page table stored in memory.
+///
+/// Entry `n` of the table, at `table + n * 4`, maps the virtual addresses
+/// `n * page_size` to `(n + 1) * page_size`. Translations are cached in a
+/// direct mapped TLB, which must be flushed when the table is modified.
+#[derive(Clone, Debug)]
+pub struct Mmu {
+    page_size: u32,
+    table: u32,
+    len: u32,
+    tlb: [Option<(u32, PageTableEntry)>; TLB_SIZE],
+}
+
+impl Mmu {
+    pub fn new(page_size: u32, table: u32, len: u32) -> Self {
+        assert!(
+            page_size.is_power_of_two() && page_size > PageTableEntry::FLAGS,
+            "page size must be a power of two larger than 15"
+        );
+
+        Self {
+            page_size,
+            table,
+            len,
+            tlb: [None; TLB_SIZE],
+        }
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    pub fn table(&self) -> u32 {
+        self.table
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn flush(&mut self) {
+        self.tlb = [None; TLB_SIZE];
+    }
+
+    fn entry(&mut self, memory: &Memory, page: u32) -> Option<PageTableEntry> {
+        let slot = page as usize % TLB_SIZE;
+
+        if let Some((cached, entry)) = self.tlb[slot] {
+            if cached == page {
+                return Some(entry);
+            }
+        }
+
+        if page >= self.len {
+            return None;
+        }
+
+        let ptr = self.table.checked_add(page.checked_mul(Word::SIZE)?)?;
+        let entry = PageTableEntry(memory.read(ptr, Word::WIDTH).ok()?.to_u32());
+
+        self.tlb[slot] = Some((page, entry));
+
+        Some(entry)
+    }
+
+    pub fn translate(&mut self, memory: &Memory, ptr: u32, access: Access) -> Result<u32, Fault> {
+        let page = ptr / self.page_size;
+        let offset = ptr % self.page_size;
+
+        match self.entry(memory, page) {
+            Some(entry)
+                if entry.is_present() && entry.permissions().contains(access.permissions()) =>
+            {
+                Ok(entry.frame(self.page_size) | offset)
+            }
+            _ => Err(Fault::PageFault {
+                address: ptr,
+                access,
+            }),
+        }
+    }
+}
@@ -1,14 +1,33 @@
-use crate::{Instruction, Word};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
 
+use crate::{
+    snapshot::{invalid_data, read_u32, write_u32},
+    Instruction, Word,
+};
+
+const VERSION: u32 = 1;
+
+#[derive(Default)]
 pub struct Program {
     data: Vec<u8>,
 }
 
 impl Program {
+    /// The first bytes of an assembled program file.
+    pub const MAGIC: &'static [u8; 4] = b"PXPG";
+
     pub const fn new() -> Self {
         Self { data: Vec::new() }
     }
 
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
     pub fn push_word(&mut self, word: Word) {
         let bytes = word.to_bytes();
         self.data.extend(bytes);
@@ -22,7 +41,53 @@ impl Program {
         self.data.len() as u32
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn bytes(&self) -> &[u8] {
         &self.data
     }
+
+    /// Writes the program as a file that [`Program::read_from`] can load.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(Self::MAGIC)?;
+        write_u32(writer, VERSION)?;
+        write_u32(writer, self.len())?;
+        writer.write_all(&self.data)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != Self::MAGIC {
+            return Err(invalid_data("not a program file"));
+        }
+
+        if read_u32(reader)? != VERSION {
+            return Err(invalid_data("unsupported program version"));
+        }
+
+        let len = read_u32(reader)?;
+
+        let mut data = Vec::new();
+        reader.take(len as u64).read_to_end(&mut data)?;
+
+        if data.len() != len as usize {
+            return Err(invalid_data("program is truncated"));
+        }
+
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid_data("program is longer than its header"));
+        }
+
+        Ok(Self { data })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
 }
@@ -0,0 +1,274 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{Abi, Access, InterruptFrame, Memory, Permissions, Registers, Trap, Word};
+
+const MAGIC: &[u8; 4] = b"PXSN";
+const VERSION: u32 = 5;
+
+/// The full state of a [`Cpu`](crate::Cpu), except for sys calls and devices.
+#[derive(Clone)]
+pub struct CpuSnapshot {
+    pub(crate) abi: Abi,
+    pub(crate) registers: Registers,
+    pub(crate) memory: Memory,
+    pub(crate) cycles: u64,
+    pub(crate) supervisor: bool,
+    /// Address and length of the installed page table.
+    pub(crate) page_table: Option<(u32, u32)>,
+    pub(crate) trap_handler: u32,
+    pub(crate) trap: Option<Trap>,
+    /// Pending interrupt lines.
+    pub(crate) interrupts: u32,
+    pub(crate) interrupts_enabled: bool,
+    pub(crate) interrupt: Option<InterruptFrame>,
+}
+
+pub(crate) fn write_u8(writer: &mut impl Write, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub(crate) fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_be_bytes())
+}
+
+pub(crate) fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_be_bytes())
+}
+
+pub(crate) fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+pub(crate) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+fn write_abi(writer: &mut impl Write, abi: &Abi) -> io::Result<()> {
+    write_u32(writer, abi.register_count)?;
+    write_u32(writer, abi.system_memory)?;
+    write_u64(writer, abi.memory_size)?;
+    write_u32(writer, abi.device_memory)?;
+    write_u8(writer, abi.system_permissions.0)?;
+    write_u32(writer, abi.page_size)?;
+    write_u32(writer, abi.interrupt_vector)?;
+    write_u32(writer, abi.stack_size)?;
+
+    match abi.stack_address {
+        Some(address) => {
+            write_u8(writer, 1)?;
+            write_u32(writer, address)?;
+        }
+        None => write_u8(writer, 0)?,
+    }
+
+    write_u32(writer, abi.entry)
+}
+
+fn read_abi(reader: &mut impl Read) -> io::Result<Abi> {
+    let abi = Abi {
+        register_count: read_u32(reader)?,
+        system_memory: read_u32(reader)?,
+        memory_size: read_u64(reader)?,
+        device_memory: read_u32(reader)?,
+        system_permissions: Permissions(read_u8(reader)?),
+        page_size: read_u32(reader)?,
+        interrupt_vector: read_u32(reader)?,
+        stack_size: read_u32(reader)?,
+        stack_address: match read_u8(reader)? {
+            0 => None,
+            _ => Some(read_u32(reader)?),
+        },
+        entry: read_u32(reader)?,
+    };
+
+    abi.validate()
+        .map_err(|error| invalid_data(&error.to_string()))?;
+
+    Ok(abi)
+}
+
+fn write_access(writer: &mut impl Write, access: Access) -> io::Result<()> {
+    write_u8(writer, access as u8)
+}
+
+fn read_access(reader: &mut impl Read) -> io::Result<Access> {
+    match read_u8(reader)? {
+        0 => Ok(Access::Read),
+        1 => Ok(Access::Write),
+        2 => Ok(Access::Execute),
+        _ => Err(invalid_data("invalid access")),
+    }
+}
+
+impl CpuSnapshot {
+    pub fn abi(&self) -> &Abi {
+        &self.abi
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+
+        write_abi(writer, &self.abi)?;
+
+        let words = self.registers.words();
+        write_u32(writer, words.len() as u32)?;
+        for word in words {
+            write_u32(writer, word.to_u32())?;
+        }
+
+        write_u64(writer, self.cycles)?;
+        write_u8(writer, self.supervisor as u8)?;
+
+        match self.page_table {
+            Some((table, len)) => {
+                write_u8(writer, 1)?;
+                write_u32(writer, table)?;
+                write_u32(writer, len)?;
+            }
+            None => write_u8(writer, 0)?,
+        }
+
+        write_u32(writer, self.trap_handler)?;
+
+        match self.trap {
+            Some(trap) => {
+                write_u8(writer, 1)?;
+                write_u32(writer, trap.eip)?;
+                write_u32(writer, trap.address)?;
+                write_access(writer, trap.access)?;
+            }
+            None => write_u8(writer, 0)?,
+        }
+
+        write_u32(writer, self.interrupts)?;
+        write_u8(writer, self.interrupts_enabled as u8)?;
+
+        match self.interrupt {
+            Some(frame) => {
+                write_u8(writer, 1)?;
+                write_u32(writer, frame.eip)?;
+                write_u32(writer, frame.erp)?;
+                write_u8(writer, frame.supervisor as u8)?;
+            }
+            None => write_u8(writer, 0)?,
+        }
+
+        self.memory.write_to(writer)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(invalid_data("not a snapshot"));
+        }
+
+        if read_u32(reader)? != VERSION {
+            return Err(invalid_data("unsupported snapshot version"));
+        }
+
+        let abi = read_abi(reader)?;
+
+        let count = read_u32(reader)?;
+        if count != abi.register_count {
+            return Err(invalid_data("register count doesn't match the abi"));
+        }
+
+        let mut words = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            words.push(Word::from_u32(read_u32(reader)?));
+        }
+
+        let cycles = read_u64(reader)?;
+        let supervisor = read_u8(reader)? != 0;
+
+        let page_table = match read_u8(reader)? {
+            0 => None,
+            _ => Some((read_u32(reader)?, read_u32(reader)?)),
+        };
+
+        let trap_handler = read_u32(reader)?;
+
+        let trap = match read_u8(reader)? {
+            0 => None,
+            _ => Some(Trap {
+                eip: read_u32(reader)?,
+                address: read_u32(reader)?,
+                access: read_access(reader)?,
+            }),
+        };
+
+        let interrupts = read_u32(reader)?;
+        let interrupts_enabled = read_u8(reader)? != 0;
+
+        let interrupt = match read_u8(reader)? {
+            0 => None,
+            _ => Some(InterruptFrame {
+                eip: read_u32(reader)?,
+                erp: read_u32(reader)?,
+                supervisor: read_u8(reader)? != 0,
+            }),
+        };
+
+        let memory = Memory::read_from(reader)?;
+
+        // memory starts at the size in the abi and the heap grows it up to device memory
+        let max_size = abi.memory_size.max(abi.device_memory as u64);
+        if memory.size() < abi.memory_size || memory.size() > max_size {
+            return Err(invalid_data("memory size doesn't match the abi"));
+        }
+
+        Ok(Self {
+            abi,
+            registers: Registers::from_words(words),
+            memory,
+            cycles,
+            supervisor,
+            page_table,
+            trap_handler,
+            trap,
+            interrupts,
+            interrupts_enabled,
+            interrupt,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+use crate::{device::mask_to_width, Device, InterruptLines, Word};
+
+/// A programmable timer that raises an interrupt every `interval` cycles, or
+/// microseconds of host time in wall clock mode.
+///
+/// Registers are words at these offsets:
+///
+///  Offset | Register
+/// --------|----------
+///  `0`    | control, see [`Timer::ENABLE`], [`Timer::REPEAT`] and [`Timer::WALL_CLOCK`]
+///  `4`    | interval
+///  `8`    | low word of the cycle counter, read only
+///  `12`   | high word of the cycle counter, read only
+///
+/// Writing the control or interval register restarts the timer. Accesses
+/// narrower than a word read and write the low bytes of a register.
+#[derive(Clone, Debug)]
+pub struct Timer {
+    line: u8,
+    control: u32,
+    interval: u32,
+    cycles: u64,
+    deadline: u64,
+    wall_deadline: Instant,
+}
+
+impl Timer {
+    pub const SIZE: u32 = 16;
+
+    pub const CONTROL: u32 = 0;
+    pub const INTERVAL: u32 = 4;
+    pub const COUNTER_LOW: u32 = 8;
+    pub const COUNTER_HIGH: u32 = 12;
+
+    pub const ENABLE: u32 = 1;
+    /// Restarts the timer when it fires, otherwise it is disabled.
+    pub const REPEAT: u32 = 2;
+    /// Counts microseconds of host time instead of cycles, which isn't deterministic.
+    pub const WALL_CLOCK: u32 = 4;
+
+    /// Creates a disabled timer that raises interrupt `line`.
+    ///
+    /// # Panics
+    /// If `line` isn't less than [`InterruptLines::COUNT`].
+    pub fn new(line: u8) -> Self {
+        assert!(
+            line < InterruptLines::COUNT,
+            "invalid interrupt line {}",
+            line
+        );
+
+        Self {
+            line,
+            control: 0,
+            interval: 0,
+            cycles: 0,
+            deadline: 0,
+            wall_deadline: Instant::now(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.control & Self::ENABLE != 0
+    }
+
+    fn restart(&mut self) {
+        if self.control & Self::WALL_CLOCK != 0 {
+            self.wall_deadline = Instant::now() + Duration::from_micros(self.interval as u64);
+        } else {
+            self.deadline = self.cycles + self.interval as u64;
+        }
+    }
+
+    fn expired(&self) -> bool {
+        if self.control & Self::WALL_CLOCK != 0 {
+            Instant::now() >= self.wall_deadline
+        } else {
+            self.cycles >= self.deadline
+        }
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: u32, width: u8) -> Word {
+        let value = match offset {
+            Self::CONTROL => self.control,
+            Self::INTERVAL => self.interval,
+            Self::COUNTER_LOW => self.cycles as u32,
+            Self::COUNTER_HIGH => (self.cycles >> 32) as u32,
+            _ => 0,
+        };
+
+        Word::from_u32(mask_to_width(value, width))
+    }
+
+    fn write(&mut self, offset: u32, data: Word, width: u8) {
+        let value = mask_to_width(data.to_u32(), width);
+
+        match offset {
+            Self::CONTROL => self.control = value,
+            Self::INTERVAL => self.interval = value,
+            _ => return,
+        }
+
+        self.restart();
+    }
+
+    fn poll(&mut self, cycles: u64) -> Option<u8> {
+        self.cycles = cycles;
+
+        if !self.is_enabled() || !self.expired() {
+            return None;
+        }
+
+        // a repeating timer without an interval would fire on every instruction
+        if self.control & Self::REPEAT != 0 && self.interval > 0 {
+            self.restart();
+        } else {
+            self.control &= !Self::ENABLE;
+        }
+
+        Some(self.line)
+    }
+
+    fn fork(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(self.clone()))
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{device::mask_to_width, Device, Word};
+
+/// A serial console that reads and writes single bytes.
+///
+/// Registers are words at these offsets:
+///
+///  Offset | Register
+/// --------|----------
+///  `0`    | data, writing outputs the low byte, reading returns the next input byte or [`Uart::EMPTY`]
+///  `4`    | status, see [`Uart::AVAILABLE`] and [`Uart::CLOSED`]
+///  `8`    | blocking data, reading waits for the next input byte
+///
+/// Reads narrower than a word return the low bytes of a register, so a byte
+/// read of the data register returns `0xff` for [`Uart::EMPTY`].
+pub struct Uart {
+    input: Receiver<u8>,
+    peeked: Option<u8>,
+    closed: bool,
+    output: Box<dyn Write>,
+}
+
+impl Uart {
+    pub const SIZE: u32 = 12;
+
+    pub const DATA: u32 = 0;
+    pub const STATUS: u32 = 4;
+    pub const BLOCKING_DATA: u32 = 8;
+
+    /// Set in the status register when an input byte can be read.
+    pub const AVAILABLE: u32 = 1;
+    /// Set in the status register when all input has been read.
+    pub const CLOSED: u32 = 2;
+
+    /// Read from the data registers when no input byte is available.
+    pub const EMPTY: u32 = u32::MAX;
+
+    /// Creates a uart reading bytes sent to `input` and writing to `output`,
+    /// input is closed when every sender is dropped.
+    pub fn new(input: Receiver<u8>, output: impl Write + 'static) -> Self {
+        Self {
+            input,
+            peeked: None,
+            closed: false,
+            output: Box::new(output),
+        }
+    }
+
+    /// Creates a uart backed by stdin and stdout, stdin is read on a separate
+    /// thread so reads don't have to block.
+    pub fn stdio() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else { break };
+
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self::new(receiver, io::stdout())
+    }
+
+    /// Creates a uart that reads `input` and writes to the returned buffer.
+    pub fn buffered(input: &[u8]) -> (Self, UartBuffer) {
+        let (sender, receiver) = mpsc::channel();
+
+        for &byte in input {
+            let _ = sender.send(byte);
+        }
+
+        let output = UartBuffer::default();
+
+        (Self::new(receiver, output.clone()), output)
+    }
+
+    fn poll_input(&mut self, block: bool) -> Option<u8> {
+        if self.peeked.is_none() && !self.closed {
+            // flush prompts before waiting for input
+            let _ = self.output.flush();
+
+            let byte = if block {
+                self.input.recv().ok()
+            } else {
+                match self.input.try_recv() {
+                    Ok(byte) => Some(byte),
+                    Err(TryRecvError::Empty) => return None,
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+
+            self.closed = byte.is_none();
+            self.peeked = byte;
+        }
+
+        self.peeked
+    }
+
+    fn read_byte(&mut self, block: bool) -> u32 {
+        self.poll_input(block);
+
+        match self.peeked.take() {
+            Some(byte) => byte as u32,
+            None => Self::EMPTY,
+        }
+    }
+
+    fn status(&mut self) -> u32 {
+        match self.poll_input(false) {
+            Some(_) => Self::AVAILABLE,
+            None if self.closed => Self::CLOSED,
+            None => 0,
+        }
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, offset: u32, width: u8) -> Word {
+        let value = match offset {
+            Self::DATA => self.read_byte(false),
+            Self::STATUS => self.status(),
+            Self::BLOCKING_DATA => self.read_byte(true),
+            _ => 0,
+        };
+
+        Word::from_u32(mask_to_width(value, width))
+    }
+
+    fn write(&mut self, offset: u32, data: Word, _width: u8) {
+        if offset == Self::DATA {
+            let _ = self.output.write_all(&[data.to_u32() as u8]);
+        }
+    }
+}
+
+impl Drop for Uart {
+    fn drop(&mut self) {
+        let _ = self.output.flush();
+    }
+}
+
+/// Output of a [`Uart::buffered`], clones share the same bytes.
+#[derive(Clone, Debug, Default)]
+pub struct UartBuffer {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl UartBuffer {
+    pub fn contents(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+}
+
+impl Write for UartBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{FileError, OpenFlags};
+
+pub(crate) trait Stream: Read + Write + Seek {}
+
+impl<S: Read + Write + Seek> Stream for S {}
+
+/// A file system mounted in a [`Vfs`].
+#[derive(Clone, Debug)]
+pub enum Mount {
+    /// A host directory, guests can't access anything outside of it.
+    Host {
+        root: PathBuf,
+        writable: bool,
+    },
+    Memory(MemoryFs),
+}
+
+impl Mount {
+    pub fn host(root: impl Into<PathBuf>) -> Self {
+        Self::Host {
+            root: root.into(),
+            writable: true,
+        }
+    }
+
+    pub fn host_read_only(root: impl Into<PathBuf>) -> Self {
+        Self::Host {
+            root: root.into(),
+            writable: false,
+        }
+    }
+}
+
+/// The file system seen by guests, made of mounts at guest paths.
+///
+/// Guest paths are resolved against `/` and `..` can't leave the root, paths
+/// that aren't in any mount don't exist.
+#[derive(Clone, Debug, Default)]
+pub struct Vfs {
+    mounts: Vec<(Vec<String>, Mount)>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mounts `mount` at the guest path `path`, replacing any mount there.
+    pub fn mount(&mut self, path: &str, mount: Mount) {
+        let path = components(path)
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        self.mounts.retain(|(other, _)| *other != path);
+        self.mounts.push((path, mount));
+    }
+
+    /// Returns the mount with the longest path containing `path` and the rest of `path`.
+    fn resolve<'a>(&self, path: &'a str) -> Result<(&Mount, Vec<&'a str>), FileError> {
+        let path = components(path);
+
+        let (prefix, mount) = self
+            .mounts
+            .iter()
+            .filter(|(prefix, _)| {
+                prefix.len() <= path.len() && prefix.iter().zip(&path).all(|(a, b)| a == b)
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .ok_or(FileError::NotFound)?;
+
+        Ok((mount, path[prefix.len()..].to_vec()))
+    }
+
+    pub(crate) fn open(&self, path: &str, flags: OpenFlags) -> Result<Box<dyn Stream>, FileError> {
+        let (mount, rest) = self.resolve(path)?;
+
+        let writes = flags.writes()
+            || flags.contains(OpenFlags::CREATE)
+            || flags.contains(OpenFlags::TRUNCATE);
+
+        match mount {
+            Mount::Host { root, writable } => {
+                if writes && !writable {
+                    return Err(FileError::PermissionDenied);
+                }
+
+                let path = host_path(root, &rest)?;
+                let write = flags.writes();
+                let append = flags.contains(OpenFlags::APPEND);
+                let truncate = write && flags.contains(OpenFlags::TRUNCATE);
+
+                // std refuses to truncate files opened for appending
+                let file = OpenOptions::new()
+                    .read(flags.contains(OpenFlags::READ))
+                    .write(write)
+                    .append(append)
+                    .create(write && flags.contains(OpenFlags::CREATE))
+                    .truncate(truncate && !append)
+                    .open(path)?;
+
+                if truncate && append {
+                    file.set_len(0)?;
+                }
+
+                Ok(Box::new(file))
+            }
+            Mount::Memory(fs) => Ok(Box::new(fs.open(&rest.join("/"), flags)?)),
+        }
+    }
+
+    /// Reads the whole file at `path`.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, FileError> {
+        let mut bytes = Vec::new();
+        self.open(path, OpenFlags::READ)?.read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+/// Splits `path` into components, `.` is skipped and `..` removes the
+/// previous component.
+fn components(path: &str) -> Vec<&str> {
+    let mut components = Vec::new();
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    components
+}
+
+/// Joins `rest` to `root`, checking that symlinks don't lead outside of `root`.
+///
+/// Every component is checked, as opening a dangling symlink with
+/// [`OpenFlags::CREATE`] would create its target.
+fn host_path(root: &Path, rest: &[&str]) -> Result<PathBuf, FileError> {
+    let root_path = root.canonicalize()?;
+    let mut path = root.to_path_buf();
+
+    for component in rest {
+        path.push(component);
+
+        match path.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // dangling symlinks can't be resolved, so they can't be checked
+                let target = path
+                    .canonicalize()
+                    .map_err(|_| FileError::PermissionDenied)?;
+
+                if !target.starts_with(&root_path) {
+                    return Err(FileError::PermissionDenied);
+                }
+            }
+            Ok(_) => {}
+            // nothing further down exists
+            Err(_) => break,
+        }
+    }
+
+    Ok(rest
+        .iter()
+        .fold(root.to_path_buf(), |path, component| path.join(component)))
+}
+
+type MemoryData = Rc<RefCell<Vec<u8>>>;
+
+/// An in-memory file system without directories, clones share the same files.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFs {
+    files: Rc<RefCell<HashMap<String, MemoryData>>>,
+}
+
+impl MemoryFs {
+    /// The largest size files can be written to, 16 MiB.
+    pub const MAX_FILE_SIZE: u64 = 0x0100_0000;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates or replaces the file at `path`, relative to the mount.
+    pub fn insert(&self, path: &str, bytes: impl Into<Vec<u8>>) {
+        let path = components(path).join("/");
+        let file = Rc::new(RefCell::new(bytes.into()));
+
+        self.files.borrow_mut().insert(path, file);
+    }
+
+    /// Returns a copy of the contents of the file at `path`.
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        let path = components(path).join("/");
+        let files = self.files.borrow();
+
+        files.get(&path).map(|file| file.borrow().clone())
+    }
+
+    fn open(&self, path: &str, flags: OpenFlags) -> Result<MemoryFile, FileError> {
+        let write = flags.writes();
+
+        if path.is_empty() || !(write || flags.contains(OpenFlags::READ)) {
+            return Err(FileError::InvalidArgument);
+        }
+
+        let mut files = self.files.borrow_mut();
+
+        let data = match files.get(path) {
+            Some(data) => data.clone(),
+            None if write && flags.contains(OpenFlags::CREATE) => {
+                let data = Rc::new(RefCell::new(Vec::new()));
+                files.insert(path.to_owned(), data.clone());
+                data
+            }
+            None => return Err(FileError::NotFound),
+        };
+
+        if write && flags.contains(OpenFlags::TRUNCATE) {
+            data.borrow_mut().clear();
+        }
+
+        Ok(MemoryFile {
+            data,
+            position: 0,
+            read: flags.contains(OpenFlags::READ),
+            write,
+            append: flags.contains(OpenFlags::APPEND),
+        })
+    }
+}
+
+struct MemoryFile {
+    data: MemoryData,
+    position: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+fn not_permitted() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "file not opened for access",
+    )
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.read {
+            return Err(not_permitted());
+        }
+
+        let data = self.data.borrow();
+
+        let start = (self.position as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.write {
+            return Err(not_permitted());
+        }
+
+        let mut data = self.data.borrow_mut();
+
+        if self.append {
+            self.position = data.len() as u64;
+        }
+
+        let end = self.position.saturating_add(buf.len() as u64);
+
+        if end > MemoryFs::MAX_FILE_SIZE {
+            return Err(io::Error::other(
+                "file would be larger than the maximum size",
+            ));
+        }
+
+        let (start, end) = (self.position as usize, end as usize);
+
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+
+        data[start..end].copy_from_slice(buf);
+        self.position = end as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let len = self.data.borrow().len() as i64;
+
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => (self.position as i64)
+                .checked_add(offset)
+                .and_then(|position| u64::try_from(position).ok()),
+            SeekFrom::End(offset) => len
+                .checked_add(offset)
+                .and_then(|position| u64::try_from(position).ok()),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{Access, Word};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    pub fn matches(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (Self::Read | Self::ReadWrite, Access::Read)
+                | (Self::Write | Self::ReadWrite, Access::Write)
+        )
+    }
+}
+
+/// Stops execution when the `len` bytes at `address` are accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub address: u32,
+    pub len: u32,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn new(address: u32, len: u32, kind: WatchKind) -> Self {
+        Self { address, len, kind }
+    }
+
+    /// Returns the start and length of the watched bytes among the `len`
+    /// bytes at `ptr`.
+    pub fn overlap(&self, ptr: u32, len: u32) -> Option<(u32, u32)> {
+        let start = (self.address as u64).max(ptr as u64);
+        let end = (self.address as u64 + self.len as u64).min(ptr as u64 + len as u64);
+
+        (start < end).then(|| (start as u32, (end - start) as u32))
+    }
+}
+
+/// A watched access, `old` and `new` hold up to a word of the watched bytes
+/// before and after the access, they are equal for reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the accessing instruction.
+    pub eip: u32,
+    pub address: u32,
+    pub len: u32,
+    pub access: Access,
+    pub old: Word,
+    pub new: Word,
+}
+
+impl std::fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} bytes at {:#x} by instruction at {:#x}, {:#x} -> {:#x}",
+            self.access,
+            self.len,
+            self.address,
+            self.eip,
+            self.old.to_u32(),
+            self.new.to_u32()
+        )
+    }
+}
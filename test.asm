@@ -5,6 +5,11 @@ main:
 	const 4096u eax
 	addi esp eax esp
 
+	// allocate space for the source
+	mov esp %12
+	const 4096u eax
+	addi esp eax esp
+
 	// save ptr to start of program
 	push %15
 
@@ -12,21 +17,36 @@ main:
 	const "test.prog" ebx
 	addi ebp ebx ebx
 
-	// write length to ebp
+	// write *char to eax
 	const 4u eax
 	addi ebx eax eax
 
-	// write *char to eax
+	// write length to ebx
 	load ebx ebx 4
-	
-	// load read call to ecx
-	const 1u ecx	
+
+	// open for reading
+	const 1u ecx
+	const 8u edx
+
+	push erp
+	// call open
+	call edx
+	pop erp
+
+	// read the source, eax is the handle
+	mov %12 ebx
+	const 4096u ecx
+	const 9u edx
 
 	push erp
 	// call read
-	call ecx
+	call edx
 	pop erp
 
+	// source in eax, length in ebx
+	mov eax ebx
+	mov %12 eax
+
 	// load parse_program
 	const parse_program ecx
 	addi ebp ecx ecx
@@ -413,23 +433,8 @@ copy:
 	pop ebx
 	pop eax
 
-copy::loop:
-	// decrement ecx
-	const 1u edx
-	subi ecx edx ecx
-
-	// load byte
-	addi eax ecx edx
-	load edx %9 1
-
-	// write byte
-	addi ebx ecx edx
-	store %9 edx 1
-
-	// loop if ecx > 0
-	const copy::loop edx
-	addi ebp edx edx
-	jmpnz edx ecx
+	// copy ecx bytes from eax to ebx
+	memcpy ebx eax ecx
 
 	// return
 	ret
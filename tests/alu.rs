@@ -0,0 +1,150 @@
+mod common;
+
+use proxy::{assemble, Opcode, Register, Stop, Word};
+
+/// Runs `source` with `eax` and `ebx` set to `args` and returns the exit code.
+fn run(source: &str, args: [u32; 2]) -> u32 {
+    let mut cpu = common::load::<()>(source);
+
+    let registers = cpu.registers_mut();
+    registers.write(Register::EAX, Word::from_u32(args[0]));
+    registers.write(Register::EBX, Word::from_u32(args[1]));
+
+    let Ok(Stop::Exit(code)) = cpu.run(&mut ()) else {
+        panic!("`{}` didn't exit", source);
+    };
+
+    code
+}
+
+/// Returns `%eax <instruction> %ebx`.
+fn binary(instruction: &str, lhs: u32, rhs: u32) -> u32 {
+    run(
+        &format!("{} eax ebx ecx\nexit ecx", instruction),
+        [lhs, rhs],
+    )
+}
+
+/// Returns `<instruction> %eax`.
+fn unary(instruction: &str, src: u32) -> u32 {
+    run(&format!("{} eax ecx\nexit ecx", instruction), [src, 0])
+}
+
+#[test]
+fn shifts_use_the_low_five_bits() {
+    assert_eq!(binary("shl", 1, 31), 0x8000_0000);
+    assert_eq!(binary("shl", 1, 32), 1);
+    assert_eq!(binary("shl", 1, 33), 2);
+
+    assert_eq!(binary("shr", 0x8000_0000, 31), 1);
+    assert_eq!(binary("shr", 0x8000_0000, 32), 0x8000_0000);
+    assert_eq!(binary("shr", 0x8000_0000, 33), 0x4000_0000);
+
+    assert_eq!(binary("sar", 0x8000_0000, 31), u32::MAX);
+    assert_eq!(binary("sar", 0x8000_0000, 32), 0x8000_0000);
+    assert_eq!(binary("sar", 0x8000_0000, 33), 0xc000_0000);
+    assert_eq!(binary("sar", 0x4000_0000, 33), 0x2000_0000);
+}
+
+#[test]
+fn rotates_wrap_bits_around() {
+    let value = 0x8000_0001;
+
+    assert_eq!(binary("rol", value, 0), value);
+    assert_eq!(binary("rol", value, 31), 0xc000_0000);
+    assert_eq!(binary("rol", value, 32), value);
+
+    assert_eq!(binary("ror", value, 0), value);
+    assert_eq!(binary("ror", value, 31), 3);
+    assert_eq!(binary("ror", value, 33), 0xc000_0000);
+}
+
+#[test]
+fn bits_are_counted() {
+    assert_eq!(unary("clz", 0), 32);
+    assert_eq!(unary("clz", 1), 31);
+    assert_eq!(unary("clz", u32::MAX), 0);
+
+    assert_eq!(unary("ctz", 0), 32);
+    assert_eq!(unary("ctz", 0x8000_0000), 31);
+    assert_eq!(unary("ctz", 0x10), 4);
+
+    assert_eq!(unary("popcnt", 0), 0);
+    assert_eq!(unary("popcnt", 0xf0f0_0001), 9);
+    assert_eq!(unary("popcnt", u32::MAX), 32);
+}
+
+#[test]
+fn bswap_reverses_bytes() {
+    assert_eq!(unary("bswap", 0x1122_3344), 0x4433_2211);
+    assert_eq!(unary("bswap", 0xff), 0xff00_0000);
+}
+
+#[test]
+#[allow(deprecated)]
+fn shift_is_an_alias_of_shr() {
+    let shift = assemble("shift eax ebx ecx").unwrap();
+    let shr = assemble("shr eax ebx ecx").unwrap();
+
+    assert_eq!(shift.bytes(), shr.bytes());
+    assert_eq!(shift.bytes()[0], Opcode::SHR.0);
+    assert_eq!(Opcode::SHIFT, Opcode::SHR);
+
+    assert_eq!(binary("shift", 0x8000_0000, 33), 0x4000_0000);
+}
+
+/// Returns `%eax <instruction> %ebx` and the carry flag it wrote.
+fn checked(instruction: &str, lhs: u32, rhs: u32) -> (u32, u32) {
+    let carry = run(
+        &format!("{} eax ebx ecx\nexit ecf", instruction),
+        [lhs, rhs],
+    );
+
+    (binary(instruction, lhs, rhs), carry)
+}
+
+#[test]
+fn carries_and_borrows_are_flagged() {
+    assert_eq!(checked("addc", u32::MAX, 1), (0, 1));
+    assert_eq!(checked("addc", u32::MAX, u32::MAX), (u32::MAX - 1, 1));
+    assert_eq!(checked("addc", u32::MAX - 1, 1), (u32::MAX, 0));
+
+    assert_eq!(checked("subc", 0, 1), (u32::MAX, 1));
+    assert_eq!(checked("subc", 1, 2), (u32::MAX, 1));
+    assert_eq!(checked("subc", 1, 1), (0, 0));
+
+    assert_eq!(checked("mulc", 0x1_0000, 0x1_0000), (0, 1));
+    assert_eq!(checked("mulc", u32::MAX, 2), (u32::MAX - 1, 1));
+    assert_eq!(checked("mulc", 0xffff, 0x1_0001), (u32::MAX, 0));
+}
+
+#[test]
+fn the_carry_is_cleared() {
+    let source = "addc eax ebx ecx\naddc ebx ebx ecx\nexit ecf";
+
+    assert_eq!(run(source, [u32::MAX, 1]), 0);
+}
+
+#[test]
+fn high_words_are_signed_or_unsigned() {
+    let min = i32::MIN as u32;
+    let minus_one = -1i32 as u32;
+
+    // 2^31 fits in the low word
+    assert_eq!(binary("mulh", min, minus_one), 0);
+    assert_eq!(binary("muli", min, minus_one), min);
+    assert_eq!(binary("mulh", min, min), 0x4000_0000);
+    assert_eq!(binary("mulh", minus_one, 1), u32::MAX);
+    assert_eq!(binary("mulh", 0x7fff_ffff, 0x7fff_ffff), 0x3fff_ffff);
+
+    assert_eq!(binary("mulhu", min, minus_one), 0x7fff_ffff);
+    assert_eq!(binary("mulhu", u32::MAX, u32::MAX), u32::MAX - 1);
+    assert_eq!(binary("mulhu", minus_one, 1), 0);
+}
+
+#[test]
+fn wrapping_arithmetic_wraps() {
+    assert_eq!(binary("addi", u32::MAX, 1), 0);
+    assert_eq!(binary("subi", 0, 1), u32::MAX);
+    assert_eq!(binary("muli", 0x1_0000, 0x1_0000), 0);
+}
@@ -0,0 +1,88 @@
+use proxy::{assemble, Abi, Cpu, Register, Stop, Word};
+
+/// Exits with the length of the second argument.
+const SOURCE: &str = "
+    const 4u ecx
+    addi ebx ecx ebx
+    load ebx ebx 4
+    load ebx ebx 4
+    exit ebx
+";
+
+fn read(cpu: &Cpu<()>, ptr: u32) -> u32 {
+    cpu.memory().read(ptr, Word::WIDTH).unwrap().to_u32()
+}
+
+fn register(cpu: &Cpu<()>, register: Register) -> u32 {
+    cpu.registers().read(register).to_u32()
+}
+
+#[test]
+fn args_and_env_follow_the_program() {
+    let abi = Abi::default();
+    let program = assemble(SOURCE).unwrap();
+
+    let mut cpu = Cpu::new(abi);
+    cpu.load_program_with_args(&program, &["prog", "hello"], &["KEY=VALUE"])
+        .unwrap();
+
+    let argv = abi.system_memory + program.len();
+    let envp = argv + 8;
+    let strings = envp + 4;
+
+    assert_eq!(register(&cpu, Register::EAX), 2);
+    assert_eq!(register(&cpu, Register::EBX), argv);
+    assert_eq!(register(&cpu, Register::ECX), 1);
+    assert_eq!(register(&cpu, Register::EDX), envp);
+
+    // pointers to strings, each a length and the bytes padded to a word
+    assert_eq!(read(&cpu, argv), strings);
+    assert_eq!(read(&cpu, argv + 4), strings + 8);
+    assert_eq!(read(&cpu, envp), strings + 20);
+
+    assert_eq!(read(&cpu, strings), 4);
+    assert_eq!(cpu.memory().read_string(strings + 4, 4).unwrap(), "prog");
+    assert_eq!(read(&cpu, strings + 8), 5);
+    assert_eq!(cpu.memory().read_string(strings + 12, 5).unwrap(), "hello");
+    assert_eq!(read(&cpu, strings + 20), 9);
+    assert_eq!(
+        cpu.memory().read_string(strings + 24, 9).unwrap(),
+        "KEY=VALUE"
+    );
+
+    // the stack starts after the strings
+    assert_eq!(cpu.registers().esp().to_u32(), strings + 36);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(5)));
+}
+
+#[test]
+fn empty_args_leave_the_stack_after_the_program() {
+    let abi = Abi::default();
+    let program = assemble("exit eax").unwrap();
+
+    let mut cpu = Cpu::new(abi);
+    cpu.load_program_with_args::<&str>(&program, &[], &[])
+        .unwrap();
+
+    let end = abi.system_memory + program.len();
+
+    assert_eq!(register(&cpu, Register::EAX), 0);
+    assert_eq!(register(&cpu, Register::EBX), end);
+    assert_eq!(register(&cpu, Register::EDX), end);
+    assert_eq!(cpu.registers().esp().to_u32(), end);
+}
+
+#[test]
+fn stack_address_overrides_the_stack() {
+    let abi = Abi {
+        stack_address: Some(0x10000),
+        ..Abi::default()
+    };
+
+    let mut cpu = Cpu::<()>::new(abi);
+    cpu.load_program_with_args(&assemble("exit eax").unwrap(), &["a"], &[])
+        .unwrap();
+
+    assert_eq!(cpu.registers().esp().to_u32(), 0x10000);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::DATA;
+use proxy::{Abi, Cpu, Fault, Register, Stop, Word};
+
+/// Loads `source` and sets `eax`, `ebx` and `ecx` to `args`.
+fn load(source: &str, args: [u32; 3]) -> Cpu<()> {
+    let mut cpu = common::load(source);
+
+    for (index, arg) in args.into_iter().enumerate() {
+        cpu.registers_mut()
+            .write(Register::new(index as u8), Word::from_u32(arg));
+    }
+
+    cpu
+}
+
+fn bytes(cpu: &Cpu<()>, ptr: u32, len: u32) -> Vec<u8> {
+    cpu.memory().read_bytes(ptr, len).unwrap().into_owned()
+}
+
+#[test]
+fn memcmp_writes_dst() {
+    let mut cpu = load("memcmp eax ebx ecx edx\nexit edx", [DATA, DATA + 16, 4]);
+    cpu.memory_mut().write_bytes(DATA, b"hell").unwrap();
+    cpu.memory_mut().write_bytes(DATA + 16, b"help").unwrap();
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(-1i32 as u32)));
+    assert_eq!(cpu.registers().read(Register::ECX).to_u32(), 4);
+}
+
+#[test]
+fn memcmp_compares_across_chunks() {
+    let mut cpu = load(
+        "memcmp eax ebx ecx edx\nexit edx",
+        [DATA, DATA + 0x4000, 0x3000],
+    );
+    cpu.memory_mut().write_bytes(DATA + 0x2fff, &[2]).unwrap();
+    cpu.memory_mut().write_bytes(DATA + 0x6fff, &[1]).unwrap();
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(1)));
+}
+
+#[test]
+fn memcpy_overlapping() {
+    let data = (0..0x3000).map(|index| index as u8).collect::<Vec<_>>();
+
+    // forwards and backwards, by more than a chunk
+    for (src, dst) in [(DATA + 0x100, DATA), (DATA, DATA + 0x100)] {
+        let mut cpu = load("memcpy eax ebx ecx\nexit ecx", [dst, src, 0x3000]);
+        cpu.memory_mut().write_bytes(src, &data).unwrap();
+
+        assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(0x3000)));
+        assert_eq!(bytes(&cpu, dst, 0x3000), data);
+    }
+}
+
+#[test]
+fn memset_fills() {
+    let mut cpu = load("memset eax ebx ecx\nexit ecx", [DATA, 0x1ab, 0x1001]);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(0x1001)));
+    assert_eq!(bytes(&cpu, DATA, 0x1001), vec![0xab; 0x1001]);
+    assert_eq!(bytes(&cpu, DATA + 0x1001, 1), [0]);
+}
+
+#[test]
+fn out_of_bounds_writes_nothing() {
+    let size = Abi::default().memory_size as u32;
+
+    let mut cpu = load("memset eax ebx ecx", [size - 0x1000, 1, 0x1001]);
+    assert!(matches!(cpu.run(&mut ()), Err(Fault::OutOfBounds { .. })));
+    assert_eq!(bytes(&cpu, size - 0x1000, 0x1000), vec![0; 0x1000]);
+
+    // the largest block faults before anything is allocated
+    let mut cpu = load("memset eax ebx ecx", [DATA, 1, u32::MAX]);
+    assert!(matches!(cpu.run(&mut ()), Err(Fault::OutOfBounds { .. })));
+    assert_eq!(bytes(&cpu, DATA, 4), [0; 4]);
+
+    let mut cpu = load("memcpy eax ebx ecx", [DATA, 0, u32::MAX]);
+    assert!(cpu.run(&mut ()).is_err());
+}
+
+#[test]
+fn zero_lengths_touch_nothing() {
+    let abi = Abi::default();
+    let sources = [
+        "memcpy eax ebx ecx",
+        "memset eax ebx ecx",
+        "memcmp eax ebx ecx edx",
+    ];
+
+    // the end of memory, past it and the read only program
+    for ptr in [abi.memory_size as u32, u32::MAX, abi.system_memory] {
+        for source in sources {
+            let mut cpu = load(&format!("{}\nexit ecx", source), [ptr, ptr, 0]);
+
+            assert_eq!(
+                cpu.run(&mut ()),
+                Ok(Stop::Exit(0)),
+                "{} at {:#x}",
+                source,
+                ptr
+            );
+        }
+    }
+}
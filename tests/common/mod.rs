@@ -0,0 +1,34 @@
+//! Helpers shared by the integration tests.
+
+// every test uses only some of them
+#![allow(dead_code)]
+
+use proxy::{assemble, Abi, Cpu, Word};
+
+/// Free memory past the programs of the tests.
+pub const DATA: u32 = 0x8000;
+
+/// Creates a cpu with `abi` and loads `source`.
+pub fn load_with<T>(abi: Abi, source: &str) -> Cpu<T> {
+    let mut cpu = Cpu::new(abi);
+    cpu.load_program(&assemble(source).unwrap()).unwrap();
+
+    cpu
+}
+
+/// Creates a cpu with the default abi and loads `source`.
+pub fn load<T>(source: &str) -> Cpu<T> {
+    load_with(Abi::default(), source)
+}
+
+/// Reads the word at `ptr` of physical memory.
+pub fn read<T>(cpu: &Cpu<T>, ptr: u32) -> u32 {
+    cpu.memory().read(ptr, Word::WIDTH).unwrap().to_u32()
+}
+
+/// Writes the word `value` at `ptr` of physical memory.
+pub fn write<T>(cpu: &mut Cpu<T>, ptr: u32, value: u32) {
+    cpu.memory_mut()
+        .write(Word::from_u32(value), ptr, Word::WIDTH)
+        .unwrap();
+}
@@ -0,0 +1,307 @@
+use proxy::{
+    assemble, Abi, AbiError, Config, ConfigError, Cpu, Files, Heap, LoadError, Permissions,
+    SysCallError,
+};
+
+/// Sys calls of a host, like the ones of the command line.
+const SYS_CALLS: [(&str, u32, u32); 3] = [("print", 0, 1), ("files", 8, 6), ("heap", 16, 2)];
+
+#[derive(Default)]
+struct State {
+    files: Files,
+    heap: Heap,
+}
+
+impl AsMut<Files> for State {
+    fn as_mut(&mut self) -> &mut Files {
+        &mut self.files
+    }
+}
+
+impl AsMut<Heap> for State {
+    fn as_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+}
+
+fn message(source: &str) -> String {
+    Config::parse(source).unwrap_err().to_string()
+}
+
+#[test]
+fn numbers_can_be_hex_and_scaled() {
+    let config = Config::parse(
+        "
+        [abi]
+        system_memory = 0x2000
+        memory_size = 1M
+        stack_size = 16k
+        device_memory = 0xf0000000
+        system_permissions = r-x
+
+        [sys_calls]
+        print = 0x20
+        ",
+    )
+    .unwrap();
+
+    let abi = Abi {
+        system_memory: 0x2000,
+        memory_size: 1 << 20,
+        stack_size: 16 << 10,
+        device_memory: 0xf000_0000,
+        system_permissions: Permissions::READ | Permissions::EXECUTE,
+        ..Abi::default()
+    };
+
+    assert_eq!(config.abi, abi);
+    assert_eq!(config.sys_calls["print"], 0x20);
+
+    let config = Config::parse("[abi]\nmemory_size = 4G").unwrap();
+    assert_eq!(config.abi.memory_size, 1 << 32);
+    assert_eq!(config.abi.validate(), Ok(()));
+}
+
+#[test]
+fn invalid_numbers_are_rejected() {
+    assert_eq!(
+        message("[abi]\nentry = 12x"),
+        "line 2: invalid number `12x` for `entry`"
+    );
+    assert_eq!(
+        message("[abi]\nentry = 0xg"),
+        "line 2: invalid number `0xg` for `entry`"
+    );
+    assert_eq!(
+        message("[abi]\nmemory_size = 99999999999G"),
+        "line 2: invalid number `99999999999G` for `memory_size`"
+    );
+    assert_eq!(
+        message("[abi]\nsystem_memory = 4G"),
+        "line 2: `4G` is too large for `system_memory`, expected at most 4294967295"
+    );
+    assert_eq!(
+        message("[abi]\nsystem_permissions = rwz"),
+        "line 2: invalid permissions `rwz`, expected a combination of r, w and x"
+    );
+}
+
+#[test]
+fn bad_sections_and_lines_are_rejected() {
+    assert_eq!(
+        message("[bogus]"),
+        "line 1: unknown section `bogus`, expected one of abi, sys_calls"
+    );
+    assert_eq!(message("[abi"), "line 1: expected `]` after `[abi`");
+    assert_eq!(message("entry = 4"), "line 1: `entry` is not in a section");
+    assert_eq!(
+        message("[abi]\nentry 4"),
+        "line 2: expected `key = value`, found `entry 4`"
+    );
+    assert_eq!(
+        message("[abi]\nstack = 4"),
+        "line 2: unknown abi setting `stack`, expected one of register_count, system_memory, \
+         memory_size, device_memory, system_permissions, page_size, interrupt_vector, \
+         stack_size, stack_address, entry"
+    );
+}
+
+#[test]
+fn errors_have_the_line_they_are_on() {
+    let source = "
+        # a comment
+        [abi] ; another comment
+
+        entry = 4 # trailing
+        register_count = many
+    ";
+
+    let error = Config::parse(source).unwrap_err();
+    assert_eq!(error.line, Some(6));
+
+    // set isn't on a line
+    let error = Config::default().set("abi", "entry", "x").unwrap_err();
+    assert_eq!(error, ConfigError::new("invalid number `x` for `entry`"));
+}
+
+#[test]
+fn every_abi_error_is_checked() {
+    let abi = Abi::default();
+
+    let cases = [
+        (
+            Abi {
+                register_count: 1000,
+                ..abi
+            },
+            AbiError::RegisterCount { count: 1000 },
+        ),
+        (
+            Abi {
+                memory_size: (1 << 32) + 1,
+                ..abi
+            },
+            AbiError::MemorySize {
+                size: (1 << 32) + 1,
+            },
+        ),
+        (
+            Abi {
+                system_memory: 0x2_0004,
+                ..abi
+            },
+            AbiError::SystemMemory {
+                size: 0x2_0004,
+                memory_size: abi.memory_size,
+            },
+        ),
+        (
+            Abi {
+                device_memory: 0x1000,
+                ..abi
+            },
+            AbiError::DeviceMemory {
+                address: 0x1000,
+                system_memory: abi.system_memory,
+            },
+        ),
+        (
+            Abi {
+                page_size: 3000,
+                ..abi
+            },
+            AbiError::PageSize { size: 3000 },
+        ),
+        (
+            Abi { entry: 2, ..abi },
+            AbiError::Misaligned {
+                field: "entry",
+                value: 2,
+            },
+        ),
+        (
+            Abi {
+                interrupt_vector: 0x2_0000 - 4,
+                ..abi
+            },
+            AbiError::InterruptVector {
+                address: 0x2_0000 - 4,
+            },
+        ),
+        (
+            Abi {
+                stack_address: Some(0x1000),
+                ..abi
+            },
+            AbiError::StackAddress { address: 0x1000 },
+        ),
+    ];
+
+    assert_eq!(abi.validate(), Ok(()));
+
+    for (abi, error) in cases {
+        assert_eq!(abi.validate(), Err(error));
+    }
+}
+
+#[test]
+fn sys_calls_are_checked() {
+    let addresses = |source: &str| {
+        Config::parse(source)
+            .unwrap()
+            .sys_call_addresses(&SYS_CALLS)
+            .map_err(|error| error.to_string())
+    };
+
+    let defaults = addresses("").unwrap();
+    assert_eq!(defaults.len(), 3);
+    assert_eq!(defaults["files"], 8);
+
+    assert_eq!(
+        addresses("[sys_calls]\nheap = 0x100").unwrap()["heap"],
+        0x100
+    );
+
+    assert_eq!(
+        addresses("[sys_calls]\nread = 1"),
+        Err("unknown sys call `read`, expected one of print, files, heap".to_owned())
+    );
+    assert_eq!(
+        addresses("[sys_calls]\nheap = 12"),
+        Err("sys calls `files` and `heap` overlap".to_owned())
+    );
+    assert_eq!(
+        addresses("[sys_calls]\nprint = 9"),
+        Err("sys calls `print` and `files` overlap".to_owned())
+    );
+    assert_eq!(
+        addresses("[sys_calls]\nheap = 0x1fff"),
+        Err("sys call `heap` at 0x1fff is not in system memory of 8192 bytes".to_owned())
+    );
+    assert_eq!(
+        addresses("[sys_calls]\nfiles = 0xfffffffc"),
+        Err("sys call `files` at 0xfffffffc is not in system memory of 8192 bytes".to_owned())
+    );
+
+    // the last addresses of system memory are fine
+    assert_eq!(
+        addresses("[sys_calls]\nheap = 0x1ffe").unwrap()["heap"],
+        0x1ffe
+    );
+}
+
+#[test]
+fn registering_past_the_address_space_fails() {
+    let mut cpu = Cpu::<State>::new(Abi::default());
+
+    assert_eq!(
+        Heap::register(&mut cpu, 0xffff_ffff),
+        Err(SysCallError {
+            address: 0xffff_ffff,
+            count: 2
+        })
+    );
+    assert_eq!(
+        Files::register(&mut cpu, 0xffff_fffc),
+        Err(SysCallError {
+            address: 0xffff_fffc,
+            count: 6
+        })
+    );
+
+    assert_eq!(Heap::register(&mut cpu, 0xffff_fffe), Ok(()));
+}
+
+#[test]
+fn the_stack_cant_be_in_the_program_or_arguments() {
+    let program = assemble("const 1u eax\nexit eax").unwrap();
+    let end = Abi::default().system_memory + program.len();
+
+    let load = |stack_address, args: &[&str]| {
+        let abi = Abi {
+            stack_address: Some(stack_address),
+            ..Abi::default()
+        };
+
+        Cpu::<()>::new(abi).load_program_with_args(&program, args, &[])
+    };
+
+    assert_eq!(
+        load(end - 4, &[]),
+        Err(LoadError::StackAddress {
+            address: end - 4,
+            end
+        })
+    );
+    assert_eq!(load(end, &[]), Ok(()));
+
+    // an argument takes a pointer, a length and its padded bytes
+    assert_eq!(
+        load(end, &["abc"]),
+        Err(LoadError::StackAddress {
+            address: end,
+            end: end + 12
+        })
+    );
+    assert_eq!(load(end + 12, &["abc"]), Ok(()));
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use proxy::{Abi, Cpu, Device, Register, Stop, Word};
+
+/// Records writes and reads back its offset and width.
+#[derive(Clone, Default)]
+struct Recorder {
+    writes: Rc<RefCell<Vec<(u32, u32, u8)>>>,
+}
+
+impl Device for Recorder {
+    fn read(&mut self, offset: u32, width: u8) -> Word {
+        Word::from_u32(offset << 8 | width as u32)
+    }
+
+    fn write(&mut self, offset: u32, data: Word, width: u8) {
+        self.writes
+            .borrow_mut()
+            .push((offset, data.to_u32(), width));
+    }
+}
+
+fn load(source: &str, address: u32) -> (Cpu<()>, Recorder) {
+    let recorder = Recorder::default();
+
+    let mut cpu = common::load(source);
+    cpu.map_device(Abi::default().device_memory, 16, recorder.clone());
+
+    let registers = cpu.registers_mut();
+    registers.write(Register::EAX, Word::from_u32(address));
+    registers.write(Register::EBX, Word::from_u32(0x1234));
+
+    (cpu, recorder)
+}
+
+#[test]
+fn accesses_are_routed_to_devices() {
+    let source = "
+        store ebx eax 4
+        const 6u edx
+        addi eax edx edx
+        store ebx edx 1
+        load edx ecx 2
+        exit ecx
+    ";
+
+    let (mut cpu, recorder) = load(source, Abi::default().device_memory);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(6 << 8 | 2)));
+    assert_eq!(*recorder.writes.borrow(), [(0, 0x1234, 4), (6, 0x1234, 1)]);
+}
+
+#[test]
+fn other_accesses_go_to_memory() {
+    let source = "
+        store ebx eax 4
+        load eax ecx 4
+        exit ecx
+    ";
+
+    let (mut cpu, recorder) = load(source, 0x8000);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(0x1234)));
+    assert!(recorder.writes.borrow().is_empty());
+    assert_eq!(cpu.memory().read(0x8000, 4).unwrap().to_u32(), 0x1234);
+}
+
+#[test]
+fn accesses_past_the_device_go_to_memory() {
+    // the last two bytes of the device and two bytes after it
+    let address = Abi::default().device_memory + 14;
+    let (mut cpu, recorder) = load("store ebx eax 4", address);
+
+    assert!(cpu.run(&mut ()).is_err());
+    assert!(recorder.writes.borrow().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "overlaps")]
+fn overlapping_devices_panic() {
+    let mut cpu = Cpu::<()>::new(Abi::default());
+
+    cpu.map_device(0x100, 16, Recorder::default());
+    cpu.map_device(0x108, 16, Recorder::default());
+}
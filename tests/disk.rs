@@ -0,0 +1,158 @@
+mod common;
+
+use common::DATA;
+use proxy::{Abi, BlockDevice, Cpu, Device, Fault, Memory, Permissions, Stop, Word};
+
+/// Reads sector 1 into `DATA` with a dma command and exits with the status.
+const SOURCE: &str = "
+    const 4026531840u eax
+    const 1u ebx
+    const 4u ecx
+    addi eax ecx ecx
+    store ebx ecx 4
+    const 8u ecx
+    addi eax ecx ecx
+    const 32768u ebx
+    store ebx ecx 4
+    const 3u ebx
+    store ebx eax 4
+    const 16u ecx
+    addi eax ecx ecx
+    load ecx edx 4
+    exit edx
+";
+
+/// A disk of three sectors, each filled with its index.
+fn disk() -> BlockDevice {
+    let size = BlockDevice::SECTOR_SIZE as usize;
+    BlockDevice::from_bytes((0..3).flat_map(|sector| vec![sector; size]).collect())
+}
+
+fn load(source: &str) -> Cpu<()> {
+    let mut cpu = common::load(source);
+    cpu.map_device(Abi::default().device_memory, BlockDevice::SIZE, disk());
+
+    cpu
+}
+
+fn command(disk: &mut BlockDevice, register: u32, value: u32) {
+    disk.write(register, Word::from_u32(value), Word::WIDTH);
+}
+
+fn status(disk: &mut BlockDevice) -> u32 {
+    disk.read(BlockDevice::STATUS, Word::WIDTH).to_u32()
+}
+
+#[test]
+fn dma_reads_sectors_into_memory() {
+    let mut cpu = load(SOURCE);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(BlockDevice::OK)));
+
+    let bytes = cpu.memory().read_bytes(DATA, 520).unwrap();
+    assert!(bytes[..512].iter().all(|&byte| byte == 1));
+    assert_eq!(bytes[512..], [0; 8]);
+}
+
+#[test]
+fn dma_reads_are_undone() {
+    let mut cpu = load(SOURCE);
+    cpu.set_history_limit(64);
+
+    // up to and including the command
+    for _ in 0..11 {
+        assert_eq!(cpu.eval_instruction(&mut ()), Ok(None));
+    }
+
+    assert_eq!(cpu.memory().read(DATA, 1).unwrap().to_u32(), 1);
+
+    assert!(cpu.step_back());
+    assert_eq!(cpu.memory().read(DATA, 1).unwrap().to_u32(), 0);
+}
+
+#[test]
+fn dma_writes_copy_memory_to_sectors() {
+    let mut memory = Memory::with_size(0x1_0000);
+    memory.write_bytes(DATA, &[7; 1024]).unwrap();
+
+    let mut disk = disk();
+    command(&mut disk, BlockDevice::SECTOR, 1);
+    command(&mut disk, BlockDevice::ADDRESS, DATA);
+    command(&mut disk, BlockDevice::COUNT, 2);
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::DMA_WRITE);
+
+    // memory is only read
+    assert!(!disk.dma(&mut memory));
+    assert_eq!(status(&mut disk), BlockDevice::OK);
+
+    command(&mut disk, BlockDevice::SECTOR, 2);
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::READ);
+
+    let offset = BlockDevice::BUFFER + BlockDevice::SECTOR_SIZE - 4;
+    assert_eq!(disk.read(offset, Word::WIDTH).to_u32(), 0x07070707);
+}
+
+#[test]
+fn dma_out_of_range_fails() {
+    let mut memory = Memory::with_size(0x1_0000);
+
+    // past the end of memory
+    let mut disk = disk();
+    command(&mut disk, BlockDevice::ADDRESS, 0xff00);
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::DMA_READ);
+
+    assert!(!disk.dma(&mut memory));
+    assert_eq!(status(&mut disk), BlockDevice::ERROR);
+    assert_eq!(memory.read(0xff00, 1).unwrap().to_u32(), 0);
+
+    // past the last sector
+    command(&mut disk, BlockDevice::ADDRESS, DATA);
+    command(&mut disk, BlockDevice::SECTOR, 2);
+    command(&mut disk, BlockDevice::COUNT, 2);
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::DMA_READ);
+
+    assert!(!disk.dma(&mut memory));
+    assert_eq!(status(&mut disk), BlockDevice::ERROR);
+
+    // without a command
+    assert!(!disk.dma(&mut memory));
+}
+
+#[test]
+fn dma_checks_permissions() {
+    let mut memory = Memory::with_size(0x1_0000);
+    memory.write_bytes(DATA, &[7; 512]).unwrap();
+    memory.protect(DATA, 512, Permissions::READ);
+
+    // into read only memory
+    let mut disk = disk();
+    command(&mut disk, BlockDevice::SECTOR, 1);
+    command(&mut disk, BlockDevice::ADDRESS, DATA);
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::DMA_READ);
+
+    assert!(!disk.dma(&mut memory));
+    assert_eq!(status(&mut disk), BlockDevice::ERROR);
+    assert_eq!(memory.read(DATA, 1).unwrap().to_u32(), 7);
+
+    // from memory that can't be read, the last byte is enough
+    memory.protect(DATA + 511, 1, Permissions::WRITE);
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::DMA_WRITE);
+
+    assert!(!disk.dma(&mut memory));
+    assert_eq!(status(&mut disk), BlockDevice::ERROR);
+
+    command(&mut disk, BlockDevice::COMMAND, BlockDevice::READ);
+    assert_eq!(disk.read(BlockDevice::BUFFER, 1).to_u32(), 1);
+}
+
+#[test]
+fn invalid_widths_fault_before_devices() {
+    let abi = Abi::default();
+    let mut cpu = load(SOURCE);
+
+    // the load is word 20, its width is the last byte
+    let width = abi.system_memory + 20 * Word::SIZE + 3;
+    cpu.memory_mut().write(Word::from_u32(3), width, 1).unwrap();
+
+    assert_eq!(cpu.run(&mut ()), Err(Fault::InvalidWidth { width: 3 }));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{read, write, DATA};
+use proxy::{Cpu, Register, Stop, Word};
+
+/// Stores `eax` at `DATA` and exits with the word that was there.
+const SOURCE: &str = "
+    const 32768u ebx
+    load ebx ecx 4
+    store eax ebx 4
+    exit ecx
+";
+
+fn load() -> Cpu<()> {
+    let mut cpu = common::load(SOURCE);
+    write(&mut cpu, DATA, 1);
+
+    cpu
+}
+
+fn data(cpu: &Cpu<()>) -> u32 {
+    read(cpu, DATA)
+}
+
+#[test]
+fn forks_start_from_the_parent_state() {
+    let mut parent = load();
+    parent.eval_instruction(&mut ()).unwrap();
+
+    let mut child = parent.fork();
+
+    assert_eq!(child.registers().words(), parent.registers().words());
+    assert_eq!(child.cycles(), parent.cycles());
+    assert_eq!(child.run(&mut ()), Ok(Stop::Exit(1)));
+}
+
+#[test]
+fn writes_are_not_shared() {
+    let mut parent = load();
+    let mut children = (2..5).map(|_| parent.fork()).collect::<Vec<_>>();
+
+    parent
+        .registers_mut()
+        .write(Register::EAX, Word::from_u32(10));
+    assert_eq!(parent.run(&mut ()), Ok(Stop::Exit(1)));
+
+    for (value, child) in (2..).zip(&mut children) {
+        child
+            .registers_mut()
+            .write(Register::EAX, Word::from_u32(value));
+
+        // every child sees the memory from before the parent wrote
+        assert_eq!(child.run(&mut ()), Ok(Stop::Exit(1)));
+        assert_eq!(data(child), value);
+    }
+
+    assert_eq!(data(&parent), 10);
+}
+
+#[test]
+fn interrupt_lines_are_not_shared() {
+    let mut parent = load();
+    parent.raise_interrupt(1);
+
+    let mut child = parent.fork();
+    parent.raise_interrupt(2);
+    child.raise_interrupt(3);
+
+    assert_eq!(parent.interrupt_lines().pending(), 0b0110);
+    assert_eq!(child.interrupt_lines().pending(), 0b1010);
+}
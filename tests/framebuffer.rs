@@ -0,0 +1,42 @@
+use proxy::{Device, Framebuffer, FramebufferError, Word};
+
+#[test]
+fn sizes_are_checked() {
+    assert_eq!(
+        Framebuffer::try_new(2048, 2048).unwrap().size(),
+        0x0100_0000
+    );
+
+    for (width, height) in [(0, 10), (10, 0), (2049, 2048), (65536, 65536)] {
+        assert_eq!(
+            Framebuffer::try_new(width, height).err(),
+            Some(FramebufferError { width, height })
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "framebuffer of 65536x65536 pixels")]
+fn new_panics_on_invalid_sizes() {
+    Framebuffer::new(65536, 65536);
+}
+
+#[test]
+fn pixels_are_written_by_width() {
+    let mut framebuffer = Framebuffer::new(2, 2);
+    let mut device = framebuffer.clone();
+
+    device.write(4, Word::from_u32(0x11223344), 4);
+    device.write(13, Word::from_u32(0xaabb), 2);
+
+    assert_eq!(device.read(6, 2).to_u32(), 0x3344);
+    assert_eq!(device.read(13, 1).to_u32(), 0xaa);
+
+    let frame = framebuffer.frame();
+    assert_eq!(frame.pixels[4..8], [0x11, 0x22, 0x33, 0x44]);
+    assert_eq!(frame.pixels[12..16], [0, 0xaa, 0xbb, 0]);
+
+    // clones share pixels
+    framebuffer.write(0, Word::from_u32(1), 1);
+    assert_eq!(device.read(0, 1).to_u32(), 1);
+}
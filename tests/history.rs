@@ -0,0 +1,127 @@
+mod common;
+
+use common::{read, DATA};
+use proxy::{Cpu, CpuState, Register};
+const FLAG: u32 = 0x9000;
+
+/// Writes 1, 2 and 3 to `DATA`, then 4 to `FLAG`.
+const SOURCE: &str = "
+    const 32768u ebx
+    const 1u ecx
+    const 1u eax
+    store eax ebx 4
+    addi eax ecx eax
+    store eax ebx 4
+    addi eax ecx eax
+    store eax ebx 4
+    addi eax ecx eax
+    const 36864u edx
+    store eax edx 4
+    exit eax
+";
+
+fn load(limit: usize) -> Cpu<()> {
+    let mut cpu = common::load(SOURCE);
+    cpu.set_history_limit(limit);
+
+    cpu
+}
+
+fn step(cpu: &mut Cpu<()>, count: usize) {
+    for _ in 0..count {
+        assert_eq!(cpu.eval_instruction(&mut ()), Ok(None));
+    }
+}
+
+#[test]
+fn step_back_undoes_registers_and_memory() {
+    let mut cpu = load(64);
+    step(&mut cpu, 8);
+
+    let registers = cpu.registers().clone();
+    let cycles = cpu.cycles();
+
+    step(&mut cpu, 3);
+    assert_eq!(read(&cpu, FLAG), 4);
+
+    for _ in 0..3 {
+        assert!(cpu.step_back());
+    }
+
+    assert_eq!(cpu.registers().words(), registers.words());
+    assert_eq!(cpu.cycles(), cycles);
+    assert_eq!(read(&cpu, FLAG), 0);
+    assert_eq!(read(&cpu, DATA), 3);
+
+    // the undone instructions run again the same way
+    step(&mut cpu, 3);
+    assert_eq!(read(&cpu, FLAG), 4);
+}
+
+#[test]
+fn history_is_bounded() {
+    let mut cpu = load(2);
+    step(&mut cpu, 6);
+
+    assert_eq!(cpu.history_len(), 2);
+    assert!(cpu.step_back());
+    assert!(cpu.step_back());
+    assert!(!cpu.step_back());
+
+    // nothing is recorded without a limit
+    let mut cpu = load(0);
+    step(&mut cpu, 6);
+
+    assert_eq!(cpu.history_len(), 0);
+    assert!(!cpu.step_back());
+}
+
+#[test]
+fn reverse_to_write_stops_before_the_last_write() {
+    let mut cpu = load(64);
+    step(&mut cpu, 11);
+
+    // the last write to DATA stored 3
+    assert!(cpu.reverse_to_write(DATA + 2));
+    assert_eq!(read(&cpu, DATA), 2);
+    assert_eq!(cpu.registers().read(Register::EAX).to_u32(), 3);
+
+    let instruction = cpu.memory().read(cpu.registers().eip().to_u32(), 1);
+    assert_eq!(instruction.unwrap().to_u32(), 5, "eip is at the store");
+
+    // nothing wrote to the end of memory
+    assert!(!cpu.reverse_to_write(0x1_0000));
+    assert_eq!(cpu.history_len(), 0);
+}
+
+fn clobber(cpu: &mut CpuState, _: &mut ()) {
+    cpu.memory.write_bytes(DATA, &[0xff; 8]).unwrap();
+}
+
+#[test]
+fn sys_calls_are_undone() {
+    let mut cpu = common::load("const 64u eax\ncall eax");
+    cpu.register_sys_call(64, clobber);
+    cpu.set_history_limit(8);
+
+    step(&mut cpu, 3);
+    assert_eq!(read(&cpu, DATA), u32::MAX);
+
+    assert!(cpu.step_back());
+    assert_eq!(read(&cpu, DATA), 0);
+    assert_eq!(cpu.registers().eip().to_u32(), 64);
+}
+
+#[test]
+fn step_back_restores_the_pending_lines() {
+    let mut cpu = load(8);
+    cpu.raise_interrupt(1);
+
+    step(&mut cpu, 1);
+
+    // a device or another thread raises a line with a handle
+    cpu.interrupt_lines().raise(2);
+
+    assert!(cpu.step_back());
+    assert_eq!(cpu.interrupt_lines().pending(), 0b10);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::thread;
+
+use proxy::{assemble, Abi, Cpu, Register, Stop, Word};
+
+/// Counts in `%10` until an interrupt sets `%11`, then exits with the count.
+const LOOP: &str = "
+    const loop ecx
+    const 8192u edx
+    addi ecx edx ecx
+    const 1u ebx
+    ei
+    loop:
+    addi %10 ebx %10
+    jmpz ecx %11
+    exit %10
+";
+
+/// Counts handled interrupts in `%11`.
+const HANDLER: &str = "
+    handler:
+    addi %11 ebx %11
+    iret
+";
+
+/// Loads `source` followed by `HANDLER` and sets the handler of `lines`.
+fn load(source: &str, lines: &[u8]) -> Cpu<()> {
+    let abi = Abi::default();
+    let handler = abi.system_memory + assemble(source).unwrap().len();
+
+    let mut cpu = common::load(&format!("{}{}", source, HANDLER));
+
+    for &line in lines {
+        let entry = abi.interrupt_vector + line as u32 * Word::SIZE;
+
+        cpu.memory_mut()
+            .write(Word::from_u32(handler), entry, Word::WIDTH)
+            .unwrap();
+    }
+
+    cpu
+}
+
+/// Runs at most `count` instructions.
+fn run_for(cpu: &mut Cpu<()>, count: usize) -> Option<Stop> {
+    (0..count).find_map(|_| cpu.eval_instruction(&mut ()).unwrap())
+}
+
+fn handled(cpu: &Cpu<()>) -> u32 {
+    cpu.registers().read(Register::new(11)).to_u32()
+}
+
+#[test]
+fn interrupts_are_delivered_once_enabled() {
+    let mut cpu = load(LOOP, &[3]);
+    cpu.raise_interrupt(3);
+
+    assert!(!cpu.interrupts_enabled());
+
+    // up to and including ei
+    assert_eq!(run_for(&mut cpu, 5), None);
+    assert!(cpu.interrupts_enabled());
+    assert_eq!(handled(&cpu), 0);
+
+    // the handler runs with interrupts disabled
+    assert_eq!(run_for(&mut cpu, 1), None);
+    assert_eq!(handled(&cpu), 1);
+    assert!(!cpu.interrupts_enabled());
+    assert_eq!(cpu.interrupt_lines().pending(), 0);
+
+    // iret returns to the loop, which exits after one iteration
+    assert_eq!(run_for(&mut cpu, 10), Some(Stop::Exit(1)));
+    assert!(cpu.interrupts_enabled());
+}
+
+#[test]
+fn disabled_interrupts_stay_pending() {
+    let mut cpu = load(&LOOP.replace("ei", ""), &[3]);
+    cpu.raise_interrupt(3);
+
+    assert_eq!(run_for(&mut cpu, 1000), None);
+    assert_eq!(handled(&cpu), 0);
+    assert_eq!(cpu.interrupt_lines().pending(), 1 << 3);
+}
+
+#[test]
+fn lines_without_handlers_are_dropped() {
+    let mut cpu = load(LOOP, &[5]);
+    cpu.raise_interrupt(4);
+    cpu.raise_interrupt(5);
+
+    assert_eq!(run_for(&mut cpu, 100), Some(Stop::Exit(1)));
+    assert_eq!(handled(&cpu), 1);
+    assert_eq!(cpu.interrupt_lines().pending(), 0);
+}
+
+#[test]
+fn lowest_line_is_delivered_first() {
+    let mut cpu = load(LOOP, &[3, 7]);
+    cpu.raise_interrupt(7);
+    cpu.raise_interrupt(3);
+
+    // ei, then the handler of line 3
+    assert_eq!(run_for(&mut cpu, 6), None);
+    assert_eq!(cpu.interrupt_lines().pending(), 1 << 7);
+
+    // iret, then the handler of line 7 before the loop runs
+    assert_eq!(run_for(&mut cpu, 2), None);
+    assert_eq!(handled(&cpu), 2);
+    assert_eq!(cpu.registers().read(Register::new(10)).to_u32(), 0);
+}
+
+#[test]
+fn lines_can_be_raised_from_other_threads() {
+    let mut cpu = load(LOOP, &[3]);
+    let lines = cpu.interrupt_lines();
+
+    thread::spawn(move || lines.raise(3)).join().unwrap();
+
+    assert_eq!(run_for(&mut cpu, 100), Some(Stop::Exit(1)));
+}
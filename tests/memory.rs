@@ -0,0 +1,312 @@
+mod common;
+
+use common::{write, DATA};
+use proxy::{assemble, Abi, Memory, MemoryError, Register, Stop, Timer, Word};
+
+/// Flat byte array that `Memory` is checked against.
+struct Model {
+    bytes: Vec<u8>,
+}
+
+impl Model {
+    fn in_bounds(&self, ptr: u32, len: u32) -> bool {
+        ptr as usize + len as usize <= self.bytes.len()
+    }
+
+    fn read(&self, ptr: u32, width: u8) -> Result<Word, MemoryError> {
+        if !matches!(width, 1 | 2 | 4) {
+            return Err(MemoryError::InvalidWidth { width });
+        }
+
+        let bytes = self.read_bytes(ptr, width as u32)?;
+
+        let mut word = [0; 4];
+        word[4 - width as usize..].copy_from_slice(&bytes);
+
+        Ok(Word::from_bytes(word))
+    }
+
+    fn read_bytes(&self, ptr: u32, len: u32) -> Result<Vec<u8>, MemoryError> {
+        if !self.in_bounds(ptr, len) {
+            return Err(MemoryError::OutOfBounds { address: ptr, len });
+        }
+
+        Ok(self.bytes[ptr as usize..ptr as usize + len as usize].to_vec())
+    }
+
+    fn write(&mut self, word: Word, ptr: u32, width: u8) -> Result<(), MemoryError> {
+        if !matches!(width, 1 | 2 | 4) {
+            return Err(MemoryError::InvalidWidth { width });
+        }
+
+        self.write_bytes(ptr, &word.to_bytes()[4 - width as usize..])
+    }
+
+    fn write_bytes(&mut self, ptr: u32, bytes: &[u8]) -> Result<(), MemoryError> {
+        let len = bytes.len() as u32;
+
+        if !self.in_bounds(ptr, len) {
+            return Err(MemoryError::OutOfBounds { address: ptr, len });
+        }
+
+        self.bytes[ptr as usize..ptr as usize + bytes.len()].copy_from_slice(bytes);
+
+        Ok(())
+    }
+}
+
+/// xorshift64, so failures are reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Picks pointers clustered around page boundaries and the end of memory.
+    fn ptr(&mut self, size: u32) -> u32 {
+        let base = match self.below(3) {
+            0 => self.below(size as u64 + 8) as u32,
+            1 => {
+                (self.below(size as u64 / Memory::PAGE_SIZE as u64 + 1) as u32) * Memory::PAGE_SIZE
+            }
+            _ => size,
+        };
+
+        base.wrapping_add(self.below(9) as u32).wrapping_sub(4)
+    }
+}
+
+fn check_against_model(seed: u64, size: u32) {
+    let mut rng = Rng(seed);
+    let mut memory = Memory::with_size(size as u64);
+    let mut model = Model {
+        bytes: vec![0; size as usize],
+    };
+
+    for step in 0..4000 {
+        let ptr = rng.ptr(size);
+
+        match rng.below(4) {
+            0 => {
+                let width = rng.below(6) as u8;
+                assert_eq!(
+                    memory.read(ptr, width),
+                    model.read(ptr, width),
+                    "seed {} step {}: read {} at {}",
+                    seed,
+                    step,
+                    width,
+                    ptr
+                );
+            }
+            1 => {
+                let width = rng.below(6) as u8;
+                let word = Word::from_u32(rng.next() as u32);
+                assert_eq!(
+                    memory.write(word, ptr, width),
+                    model.write(word, ptr, width),
+                    "seed {} step {}: write {} at {}",
+                    seed,
+                    step,
+                    width,
+                    ptr
+                );
+            }
+            2 => {
+                let len = rng.below(Memory::PAGE_SIZE as u64 * 2) as u32;
+                assert_eq!(
+                    memory.read_bytes(ptr, len).map(|bytes| bytes.into_owned()),
+                    model.read_bytes(ptr, len),
+                    "seed {} step {}: read_bytes {} at {}",
+                    seed,
+                    step,
+                    len,
+                    ptr
+                );
+            }
+            _ => {
+                let len = rng.below(Memory::PAGE_SIZE as u64 * 2) as usize;
+                let fill = rng.next() as u8;
+                let bytes = (0..len)
+                    .map(|i| fill.wrapping_add(i as u8))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    memory.write_bytes(ptr, &bytes),
+                    model.write_bytes(ptr, &bytes),
+                    "seed {} step {}: write_bytes {} at {}",
+                    seed,
+                    step,
+                    len,
+                    ptr
+                );
+            }
+        }
+    }
+
+    assert_eq!(
+        memory.read_bytes(0, size).unwrap().into_owned(),
+        model.bytes,
+        "seed {}: final contents",
+        seed
+    );
+}
+
+#[test]
+fn matches_model() {
+    for seed in 1..=16u64 {
+        check_against_model(
+            seed.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+            3 * Memory::PAGE_SIZE + 123,
+        );
+    }
+}
+
+#[test]
+fn exact_bounds() {
+    let mut memory = Memory::with_size(16);
+
+    assert!(memory.write(Word::from_u32(0x0102_0304), 12, 4).is_ok());
+    assert_eq!(memory.read(15, 1), Ok(Word::from_u32(4)));
+    assert_eq!(
+        memory.read(13, 4),
+        Err(MemoryError::OutOfBounds {
+            address: 13,
+            len: 4
+        })
+    );
+    assert!(memory.read_bytes(16, 0).is_ok());
+    assert!(memory.read_bytes(17, 0).is_err());
+    assert_eq!(
+        memory.read(0, 3),
+        Err(MemoryError::InvalidWidth { width: 3 })
+    );
+}
+
+#[test]
+fn full_address_space() {
+    let mut memory = Memory::with_size(1 << 32);
+
+    assert_eq!(memory.size(), 1 << 32);
+    assert_eq!(memory.resident_size(), 0);
+
+    assert!(memory.write(Word::from_u32(7), u32::MAX - 3, 4).is_ok());
+    assert_eq!(memory.read(u32::MAX, 1), Ok(Word::from_u32(7)));
+    assert!(memory.write(Word::from_u32(7), u32::MAX - 2, 4).is_err());
+    assert!(memory.write_bytes(u32::MAX, &[1, 2]).is_err());
+    assert_eq!(memory.resident_size(), Memory::PAGE_SIZE as u64);
+}
+
+#[test]
+fn pages_are_allocated_when_written() {
+    let page = Memory::PAGE_SIZE as u64;
+    let mut memory = Memory::with_size(1 << 20);
+
+    assert_eq!(memory.resident_size(), 0);
+
+    // reading and writing zeros leaves pages unallocated
+    assert_eq!(memory.read_bytes(0, 1 << 20).unwrap().len(), 1 << 20);
+    memory.write_bytes(0x1000, &[0; 0x3000]).unwrap();
+    memory.write(Word::from_u32(0), 0x8000, 4).unwrap();
+    assert_eq!(memory.resident_size(), 0);
+
+    // a write across a page boundary allocates both pages
+    memory.write(Word::from_u32(u32::MAX), 0x2ffe, 4).unwrap();
+    assert_eq!(memory.resident_size(), 2 * page);
+
+    // zeros over allocated pages are written
+    memory.write_bytes(0x2ffe, &[0; 4]).unwrap();
+    assert_eq!(memory.read(0x2ffe, 4), Ok(Word::from_u32(0)));
+    assert_eq!(memory.resident_size(), 2 * page);
+
+    // clones share pages until they write
+    let mut clone = memory.clone();
+    clone.write_bytes(0x5000, &[1]).unwrap();
+    assert_eq!(memory.resident_size(), 2 * page);
+    assert_eq!(clone.resident_size(), 3 * page);
+}
+
+#[test]
+fn four_gib_cpus_run() {
+    let abi = Abi {
+        memory_size: 1 << 32,
+        ..Abi::default()
+    };
+
+    // the end of memory is past the start of the devices
+    let ptr = u32::MAX - 3;
+    let timer = abi.device_memory + Timer::INTERVAL;
+
+    let source = "store eax ebx 4\nstore eax edx 4\nload ebx ecx 4\nexit ecx";
+    let mut cpu = common::load_with::<()>(abi, source);
+    cpu.map_device(abi.device_memory, Timer::SIZE, Timer::new(0));
+
+    let registers = cpu.registers_mut();
+    registers.write(Register::EAX, Word::from_u32(77));
+    registers.write(Register::EBX, Word::from_u32(ptr));
+    registers.write(Register::EDX, Word::from_u32(timer));
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(77)));
+    assert_eq!(cpu.memory().size(), 1 << 32);
+    assert_eq!(common::read(&cpu, ptr), 77);
+
+    // the timer hides the memory it is mapped over
+    assert_eq!(common::read(&cpu, timer), 0);
+    assert!(cpu.memory().resident_size() <= 4 * Memory::PAGE_SIZE as u64);
+}
+
+/// Runs `instruction` reading `width` bytes of `value` at `DATA`.
+fn load(instruction: &str, value: u32, width: u8) -> u32 {
+    let source = format!("{} eax ebx {}\nexit ebx", instruction, width);
+
+    let mut cpu = common::load::<()>(&source);
+    write(&mut cpu, DATA, value << (32 - width as u32 * 8));
+    cpu.registers_mut()
+        .write(Register::EAX, Word::from_u32(DATA));
+
+    let Ok(Stop::Exit(code)) = cpu.run(&mut ()) else {
+        panic!("`{}` didn't exit", source);
+    };
+
+    code
+}
+
+#[test]
+fn loads_sign_extend() {
+    assert_eq!(load("loads", 0x80, 1) as i32, -128);
+    assert_eq!(load("loads", 0x7f, 1) as i32, 127);
+    assert_eq!(load("loads", 0xff, 1) as i32, -1);
+    assert_eq!(load("loads", 0x8000, 2) as i32, -32768);
+    assert_eq!(load("loads", 0x7fff, 2) as i32, 32767);
+    assert_eq!(load("loads", 0x8000_0000, 4) as i32, i32::MIN);
+
+    // load zero extends the same bytes
+    assert_eq!(load("load", 0x80, 1), 0x80);
+    assert_eq!(load("load", 0x8000, 2), 0x8000);
+}
+
+#[test]
+fn invalid_widths_dont_assemble() {
+    for instruction in ["load", "loads", "store"] {
+        for width in [0, 3, 8] {
+            let source = format!("{} eax ebx {}", instruction, width);
+
+            assert_eq!(
+                assemble(&source).err().unwrap().to_string(),
+                format!("line 1: invalid width {}, expected 1, 2 or 4", width)
+            );
+        }
+    }
+
+    assert_eq!(
+        assemble("load eax ebx four").err().unwrap().to_string(),
+        "line 1: expected u8"
+    );
+}
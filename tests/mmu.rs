@@ -0,0 +1,211 @@
+mod common;
+
+use proxy::{Abi, Access, Cpu, Fault, Opcode, PageTableEntry, Permissions, Register, Stop, Word};
+
+const TABLE: u32 = 0x10000;
+const PAGES: u32 = 8;
+
+/// Virtual page mapped to `FRAME` by `load`.
+const PAGE: u32 = 0x4000;
+const FRAME: u32 = 0x9000;
+
+/// Installs the page table, runs `{}` and enters user mode at the `user` label.
+const SETUP: &str = "
+    const 65536u eax
+    const 8u ebx
+    setpt eax ebx
+    {}
+    const user ecx
+    const 8192u edx
+    addi ecx edx ecx
+    user ecx
+";
+
+fn map(cpu: &mut Cpu<()>, page: u32, frame: u32, permissions: Permissions) {
+    let entry = PageTableEntry::new(frame, permissions);
+    let ptr = TABLE + page / Abi::default().page_size * Word::SIZE;
+
+    cpu.memory_mut()
+        .write(Word::from_u32(entry.0), ptr, Word::WIDTH)
+        .unwrap();
+}
+
+/// Loads `SETUP` running `supervisor` followed by `source`, with the program
+/// identity mapped and `PAGE` mapped to `FRAME`.
+fn load_with(supervisor: &str, source: &str) -> Cpu<()> {
+    let abi = Abi::default();
+    let source = SETUP.replace("{}", supervisor) + source;
+
+    let mut cpu = common::load(&source);
+
+    map(
+        &mut cpu,
+        abi.system_memory,
+        abi.system_memory,
+        Permissions::READ | Permissions::EXECUTE,
+    );
+    map(
+        &mut cpu,
+        PAGE,
+        FRAME,
+        Permissions::READ | Permissions::WRITE,
+    );
+
+    cpu
+}
+
+fn load(source: &str) -> Cpu<()> {
+    load_with("", source)
+}
+
+/// Runs until the cpu is in user mode.
+fn enter_user(cpu: &mut Cpu<()>) {
+    while cpu.is_supervisor() {
+        assert_eq!(cpu.eval_instruction(&mut ()), Ok(None));
+    }
+}
+
+#[test]
+fn user_accesses_are_translated() {
+    let mut cpu = load(
+        "
+        user:
+        const 16384u eax
+        const 77u ebx
+        store ebx eax 4
+        load eax ecx 4
+        exit ecx
+        ",
+    );
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(77)));
+    assert_eq!(cpu.memory().read(FRAME, 4).unwrap().to_u32(), 77);
+    assert_eq!(cpu.memory().read(PAGE, 4).unwrap().to_u32(), 0);
+    assert_eq!(cpu.mmu().map(|mmu| mmu.len()), Some(PAGES));
+}
+
+#[test]
+fn unmapped_pages_fault() {
+    let mut cpu = load(
+        "
+        user:
+        const 20480u eax
+        store eax eax 4
+        ",
+    );
+
+    assert_eq!(
+        cpu.run(&mut ()),
+        Err(Fault::PageFault {
+            address: 0x5000,
+            access: Access::Write
+        })
+    );
+}
+
+#[test]
+fn page_permissions_are_checked() {
+    // the program is mapped read and execute only
+    let mut cpu = load(
+        "
+        user:
+        const 8192u eax
+        store eax eax 4
+        ",
+    );
+
+    assert_eq!(
+        cpu.run(&mut ()),
+        Err(Fault::PageFault {
+            address: 0x2000,
+            access: Access::Write
+        })
+    );
+}
+
+#[test]
+fn privileged_instructions_fault_in_user_mode() {
+    let mut cpu = load("user:\ntlbflush");
+
+    assert_eq!(
+        cpu.run(&mut ()),
+        Err(Fault::Privileged {
+            opcode: Opcode::TLBFLUSH
+        })
+    );
+}
+
+#[test]
+fn page_faults_are_delivered_to_the_trap_handler() {
+    // the handler maps the faulting page to 0xa000 and retries the access, it
+    // only uses registers the user code doesn't
+    let supervisor = "
+        const handler eax
+        const 8192u ebx
+        addi eax ebx eax
+        settrap eax
+    ";
+
+    let mut cpu = load_with(
+        supervisor,
+        "
+        handler:
+        trapinfo %10 %11
+        const 12u %11
+        shr %10 %11 %10
+        const 4u %11
+        muli %10 %11 %10
+        const 65536u %11
+        addi %10 %11 %10
+        const 40971u %11
+        store %11 %10 4
+        tlbflush
+        tret
+
+        user:
+        const 20480u eax
+        const 5u ebx
+        store ebx eax 4
+        exit ebx
+        ",
+    );
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(5)));
+    assert_eq!(cpu.memory().read(0xa000, 4).unwrap().to_u32(), 5);
+}
+
+#[test]
+fn translations_are_cached_until_flushed() {
+    let mut cpu = load(
+        "
+        user:
+        load eax ebx 4
+        load eax ecx 4
+        ",
+    );
+
+    cpu.memory_mut()
+        .write(Word::from_u32(1), FRAME, Word::WIDTH)
+        .unwrap();
+    cpu.memory_mut()
+        .write(Word::from_u32(2), 0xa000, Word::WIDTH)
+        .unwrap();
+
+    enter_user(&mut cpu);
+    cpu.registers_mut()
+        .write(Register::EAX, Word::from_u32(PAGE));
+
+    cpu.eval_instruction(&mut ()).unwrap();
+    assert_eq!(cpu.registers().read(Register::EBX).to_u32(), 1);
+
+    // the stale translation is used until the tlb is flushed
+    map(
+        &mut cpu,
+        PAGE,
+        0xa000,
+        Permissions::READ | Permissions::WRITE,
+    );
+
+    cpu.eval_instruction(&mut ()).unwrap();
+    assert_eq!(cpu.registers().read(Register::ECX).to_u32(), 1);
+}
@@ -0,0 +1,143 @@
+use proxy::{
+    assemble, disassemble, Abi, Args, Cpu, Decoded, Instruction, LoadError, MemoryError, Opcode,
+    Program,
+};
+
+const SOURCE: &str = "
+    const 7u eax
+    const -1i ebx
+    memcmp eax ebx ecx edx
+    load eax ebx 2
+    addi eax ebx ecx
+    ret
+";
+
+fn bytes(program: &Program) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    program.write_to(&mut bytes).unwrap();
+
+    bytes
+}
+
+fn read(bytes: &[u8]) -> std::io::Result<Program> {
+    Program::read_from(&mut &bytes[..])
+}
+
+#[test]
+fn program_files_round_trip() {
+    let program = assemble(SOURCE).unwrap();
+    let bytes = bytes(&program);
+
+    assert!(bytes.starts_with(Program::MAGIC));
+    assert_eq!(read(&bytes).unwrap().bytes(), program.bytes());
+}
+
+#[test]
+fn truncated_and_oversized_programs_are_rejected() {
+    let bytes = bytes(&assemble(SOURCE).unwrap());
+
+    for len in [0, 3, 8, 11, bytes.len() - 1] {
+        assert!(read(&bytes[..len]).is_err(), "{} bytes", len);
+    }
+
+    let mut longer = bytes.clone();
+    longer.extend([0; 4]);
+    assert!(read(&longer).is_err());
+
+    // a header claiming more than there is
+    let mut header = bytes.clone();
+    header[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(read(&header).is_err());
+
+    let mut magic = bytes.clone();
+    magic[0] = b'X';
+    assert!(read(&magic).is_err());
+
+    let mut version = bytes;
+    version[7] = 2;
+    assert!(read(&version).is_err());
+}
+
+#[test]
+fn programs_larger_than_memory_dont_load() {
+    let abi = Abi::default();
+    let program = Program::from_bytes(vec![0; abi.memory_size as usize]);
+
+    let error = Cpu::<()>::new(abi).load_program(&program).unwrap_err();
+    assert!(matches!(
+        error,
+        LoadError::Memory(MemoryError::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn disassembling_shows_the_source() {
+    let program = assemble(SOURCE).unwrap();
+
+    let lines = disassemble(&program)
+        .into_iter()
+        .map(|(offset, decoded)| format!("{} {}", offset, decoded))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        lines,
+        [
+            "0 const 7u eax",
+            "8 const 4294967295u ebx",
+            "16 memcmp eax ebx ecx edx",
+            "24 load eax ebx 2",
+            "28 addi eax ebx ecx",
+            "32 ret",
+        ]
+    );
+}
+
+#[test]
+fn every_opcode_reassembles() {
+    let mut count = 0;
+
+    for opcode in 1..=u8::MAX {
+        let instruction = Instruction {
+            opcode: Opcode(opcode),
+            args: Args::from_bytes([1, 2, 4]),
+        };
+
+        let source = match opcode {
+            9 => format!("{} %11", instruction),
+            _ => instruction.to_string(),
+        };
+
+        if source.starts_with("invalid opcode") {
+            continue;
+        }
+
+        count += 1;
+
+        let program = assemble(&source).unwrap_or_else(|error| panic!("{}: {}", source, error));
+        assert_eq!(program.bytes()[0], opcode, "{}", source);
+
+        let decoded = disassemble(&program)
+            .into_iter()
+            .map(|(_, decoded)| decoded.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(decoded, [source]);
+    }
+
+    // every opcode but `const`
+    assert_eq!(count, 62);
+
+    // and data isn't mistaken for instructions
+    let data = Program::from_bytes(vec![0xff, 0, 0, 0]);
+    assert!(matches!(disassemble(&data)[0].1, Decoded::Data(_)));
+}
+
+#[test]
+fn string_constants_are_padded() {
+    let program = assemble("const \"\" eax\nconst \"abcde\" ebx\nexit eax").unwrap();
+    let code = assemble("const 0u eax\nconst 0u ebx\nexit eax").unwrap();
+
+    // a length word each, the empty string has no bytes
+    let strings = &program.bytes()[code.bytes().len()..];
+    assert_eq!(strings, b"\0\0\0\0\0\0\0\x05abcde\0\0\0");
+}
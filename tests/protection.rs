@@ -0,0 +1,139 @@
+mod common;
+
+use common::DATA;
+use proxy::{
+    Abi, Access, Cpu, Fault, Files, MemoryFs, Mount, OpenFlags, Register, Stop, Vfs, Word,
+};
+
+fn load<T>(source: &str, eax: u32) -> Cpu<T> {
+    let mut cpu = common::load(source);
+    cpu.registers_mut()
+        .write(Register::EAX, Word::from_u32(eax));
+
+    cpu
+}
+
+#[test]
+fn writing_the_program_faults() {
+    let program = Abi::default().system_memory;
+    let mut cpu = load::<()>("store eax eax 4", program);
+
+    assert_eq!(
+        cpu.run(&mut ()),
+        Err(Fault::Protection {
+            address: program,
+            access: Access::Write
+        })
+    );
+    assert_eq!(cpu.memory().read(program, 1).unwrap().to_u32(), 5);
+}
+
+#[test]
+fn writing_system_memory_faults() {
+    let mut cpu = load::<()>("store eax eax 4", 0);
+
+    assert_eq!(
+        cpu.run(&mut ()),
+        Err(Fault::Protection {
+            address: 0,
+            access: Access::Write
+        })
+    );
+}
+
+#[test]
+fn executing_data_faults() {
+    let mut cpu = load::<()>("jmp eax", DATA);
+
+    assert_eq!(
+        cpu.run(&mut ()),
+        Err(Fault::Protection {
+            address: DATA,
+            access: Access::Execute
+        })
+    );
+}
+
+#[test]
+fn data_is_readable_and_writable() {
+    let mut cpu = load::<()>("store eax eax 4\nload eax ebx 4\nexit ebx", DATA);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(DATA)));
+}
+
+struct State(Files);
+
+impl AsMut<Files> for State {
+    fn as_mut(&mut self) -> &mut Files {
+        &mut self.0
+    }
+}
+
+/// Opens `/file` with `contents` and reads `len` bytes of it into `%buffer`,
+/// exits with the error code and leaves the number of bytes read in `eax`.
+fn read_into(buffer: u32, len: u32, contents: &[u8]) -> (Cpu<State>, State) {
+    let source = format!(
+        "
+        const 32u edx
+        call edx
+        const {}u ebx
+        const {}u ecx
+        const 33u edx
+        call edx
+        exit ebx
+        ",
+        buffer, len
+    );
+
+    let fs = MemoryFs::new();
+    fs.insert("file", contents);
+
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Mount::Memory(fs));
+
+    let mut cpu = load(&source, DATA);
+    cpu.memory_mut().write_bytes(DATA, b"/file").unwrap();
+
+    let registers = cpu.registers_mut();
+    registers.write(Register::EBX, Word::from_u32(5));
+    registers.write(Register::ECX, Word::from_u32(OpenFlags::READ.0));
+
+    Files::register(&mut cpu, 32).unwrap();
+
+    (cpu, State(Files::new(vfs)))
+}
+
+#[test]
+fn sys_calls_write_writable_buffers() {
+    let (mut cpu, mut state) = read_into(DATA + 16, 4, b"data");
+
+    assert_eq!(cpu.run(&mut state), Ok(Stop::Exit(0)));
+    assert_eq!(&*cpu.memory().read_bytes(DATA + 16, 4).unwrap(), b"data");
+}
+
+#[test]
+fn sys_calls_check_permissions() {
+    let program = Abi::default().system_memory;
+    let (mut cpu, mut state) = read_into(program, 4, b"data");
+
+    let before = cpu.memory().read_bytes(program, 4).unwrap().into_owned();
+
+    // bad buffer address
+    assert_eq!(cpu.run(&mut state), Ok(Stop::Exit(6)));
+    assert_eq!(*cpu.memory().read_bytes(program, 4).unwrap(), before);
+}
+
+#[test]
+fn sys_calls_read_large_buffers() {
+    let contents = (0..10_000).map(|i| i as u8).collect::<Vec<_>>();
+    let buffer = DATA + 16;
+
+    // up to the end of memory
+    let len = (Abi::default().memory_size - buffer as u64) as u32;
+    let (mut cpu, mut state) = read_into(buffer, len, &contents);
+
+    assert_eq!(cpu.run(&mut state), Ok(Stop::Exit(0)));
+    assert_eq!(cpu.registers().read(Register::EAX).to_u32(), 10_000);
+    assert_eq!(*cpu.memory().read_bytes(buffer, 10_000).unwrap(), contents);
+    assert_eq!(cpu.memory().read(buffer + 10_000, 1).unwrap().to_u32(), 0);
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use proxy::{Abi, Cpu, CpuSnapshot, Stop};
+
+fn snapshot_bytes() -> (Cpu<()>, Vec<u8>) {
+    let source = "
+        const 7u eax
+        const 32768u ebx
+        store eax ebx 4
+        exit eax
+    ";
+
+    let mut cpu = common::load(source);
+
+    // stop after the store
+    for _ in 0..3 {
+        cpu.eval_instruction(&mut ()).unwrap();
+    }
+
+    let mut bytes = Vec::new();
+    cpu.snapshot().write_to(&mut bytes).unwrap();
+
+    (cpu, bytes)
+}
+
+/// Returns the offset of the size of memory, the first time the size is
+/// written after the one in the abi.
+fn memory_size_offset(bytes: &[u8]) -> usize {
+    let size = Abi::default().memory_size.to_be_bytes();
+    let after_abi_size = 24;
+
+    after_abi_size
+        + bytes[after_abi_size..]
+            .windows(8)
+            .position(|window| window == size)
+            .unwrap()
+}
+
+fn read(bytes: &[u8]) -> std::io::Result<CpuSnapshot> {
+    CpuSnapshot::read_from(&mut &bytes[..])
+}
+
+#[test]
+fn round_trip() {
+    let (mut cpu, bytes) = snapshot_bytes();
+    let snapshot = read(&bytes).unwrap();
+
+    let mut again = Vec::new();
+    snapshot.write_to(&mut again).unwrap();
+    assert_eq!(bytes, again);
+
+    // restoring continues where the snapshot was taken
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(7)));
+
+    let mut restored = Cpu::new(Abi::default());
+    restored.restore(&snapshot);
+
+    assert_eq!(restored.memory().read(0x8000, 4).unwrap().to_u32(), 7);
+    assert_eq!(restored.run(&mut ()), Ok(Stop::Exit(7)));
+}
+
+#[test]
+fn a_stack_at_address_zero_round_trips() {
+    let abi = Abi {
+        system_memory: 0,
+        stack_address: Some(0),
+        ..Abi::default()
+    };
+
+    let mut bytes = Vec::new();
+    Cpu::<()>::new(abi).snapshot().write_to(&mut bytes).unwrap();
+
+    assert_eq!(*read(&bytes).unwrap().abi(), abi);
+}
+
+#[test]
+fn truncated_snapshots_are_rejected() {
+    let (_, bytes) = snapshot_bytes();
+
+    for len in [0, 3, 8, 40, bytes.len() / 2, bytes.len() - 1] {
+        assert!(read(&bytes[..len]).is_err(), "{} bytes", len);
+    }
+}
+
+#[test]
+fn bad_headers_are_rejected() {
+    let (_, bytes) = snapshot_bytes();
+
+    let mut magic = bytes.clone();
+    magic[0] = b'X';
+    assert!(read(&magic).is_err());
+
+    let mut version = bytes.clone();
+    version[7] ^= 0xff;
+    assert!(read(&version).is_err());
+}
+
+#[test]
+fn memory_size_is_checked_against_the_abi() {
+    let (_, bytes) = snapshot_bytes();
+    let offset = memory_size_offset(&bytes);
+
+    let sizes = [
+        // smaller than the abi
+        4096,
+        // past device memory
+        Abi::default().device_memory as u64 + 4096,
+        // past the address space
+        1 << 40,
+    ];
+
+    for size in sizes {
+        let mut corrupt = bytes.clone();
+        corrupt[offset..offset + 8].copy_from_slice(&size.to_be_bytes());
+
+        let error = read(&corrupt).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", size);
+    }
+}
+
+#[test]
+fn regions_past_memory_are_rejected() {
+    let (_, bytes) = snapshot_bytes();
+    let offset = memory_size_offset(&bytes);
+
+    // the end of the first region
+    let end = offset + 8 + 4 + 8;
+    let mut corrupt = bytes.clone();
+    corrupt[end..end + 8].copy_from_slice(&(1u64 << 32).to_be_bytes());
+
+    assert!(read(&corrupt).is_err());
+}
+
+#[test]
+fn restore_sets_the_pending_lines() {
+    let (mut cpu, _) = snapshot_bytes();
+    cpu.raise_interrupt(1);
+
+    let snapshot = cpu.snapshot();
+    let lines = cpu.interrupt_lines();
+
+    lines.raise(2);
+    cpu.restore(&snapshot);
+
+    assert_eq!(lines.pending(), 0b10);
+}
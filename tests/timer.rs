@@ -0,0 +1,132 @@
+mod common;
+
+use proxy::{assemble, Abi, Device, Register, Stop, Timer, Word};
+
+const LINE: u8 = 2;
+
+/// A timer that was polled at cycle 0 and then programmed.
+fn timer(interval: u32, control: u32) -> Timer {
+    let mut timer = Timer::new(LINE);
+    timer.poll(0);
+    timer.write(Timer::INTERVAL, Word::from_u32(interval), 4);
+    timer.write(Timer::CONTROL, Word::from_u32(control), 4);
+
+    timer
+}
+
+fn fired(timer: &mut Timer, cycles: impl IntoIterator<Item = u64>) -> Vec<u64> {
+    cycles
+        .into_iter()
+        .filter(|&cycles| timer.poll(cycles).is_some())
+        .collect()
+}
+
+#[test]
+fn one_shot_timers_fire_once() {
+    let mut timer = timer(10, Timer::ENABLE);
+
+    assert_eq!(timer.poll(10), Some(LINE));
+    assert!(!timer.is_enabled());
+    assert_eq!(fired(&mut timer, 11..100), []);
+}
+
+#[test]
+fn repeating_timers_restart() {
+    let mut timer = timer(10, Timer::ENABLE | Timer::REPEAT);
+
+    assert_eq!(fired(&mut timer, 1..=35), [10, 20, 30]);
+    assert!(timer.is_enabled());
+}
+
+#[test]
+fn disabled_timers_dont_fire() {
+    let mut timer = timer(10, Timer::REPEAT);
+
+    assert_eq!(fired(&mut timer, 1..100), []);
+}
+
+#[test]
+fn writing_restarts_the_timer() {
+    let mut timer = timer(10, Timer::ENABLE);
+
+    timer.poll(8);
+    timer.write(Timer::INTERVAL, Word::from_u32(10), 4);
+
+    assert_eq!(fired(&mut timer, 9..30), [18]);
+}
+
+#[test]
+fn the_counter_is_readable() {
+    let mut timer = Timer::new(LINE);
+    timer.poll(0x1_0000_0005);
+
+    assert_eq!(timer.read(Timer::COUNTER_LOW, 4).to_u32(), 5);
+    assert_eq!(timer.read(Timer::COUNTER_HIGH, 4).to_u32(), 1);
+}
+
+#[test]
+fn narrow_accesses_use_the_low_bytes() {
+    let mut timer = timer(0x1234_5678, 0);
+
+    assert_eq!(timer.read(Timer::INTERVAL, 1).to_u32(), 0x78);
+    assert_eq!(timer.read(Timer::INTERVAL, 2).to_u32(), 0x5678);
+    assert_eq!(timer.read(Timer::INTERVAL, 4).to_u32(), 0x1234_5678);
+
+    timer.write(Timer::INTERVAL, Word::from_u32(0xabcd_ef01), 2);
+    assert_eq!(timer.read(Timer::INTERVAL, 4).to_u32(), 0xef01);
+
+    timer.write(Timer::CONTROL, Word::from_u32(0x100 | Timer::ENABLE), 1);
+    assert_eq!(timer.read(Timer::CONTROL, 4).to_u32(), Timer::ENABLE);
+}
+
+#[test]
+#[should_panic(expected = "invalid interrupt line 32")]
+fn invalid_lines_panic() {
+    Timer::new(32);
+}
+
+#[test]
+fn timers_interrupt_the_cpu() {
+    // programs a repeating timer and exits after three interrupts
+    let source = "
+        const 4026531840u eax
+        const 100u ebx
+        const 4u ecx
+        addi eax ecx ecx
+        store ebx ecx 4
+        const 3u ebx
+        store ebx eax 4
+        const 1u ebx
+        const 3u ecx
+        const loop edx
+        const 8192u %12
+        addi edx %12 edx
+        ei
+        loop:
+        bne edx %11 ecx
+        exit %11
+    ";
+
+    let handler = "
+        handler:
+        addi %11 ebx %11
+        iret
+    ";
+
+    let abi = Abi::default();
+    let address = abi.system_memory + assemble(source).unwrap().len();
+
+    let mut cpu = common::load(&format!("{}{}", source, handler));
+    cpu.map_device(abi.device_memory, Timer::SIZE, Timer::new(LINE));
+    cpu.memory_mut()
+        .write(
+            Word::from_u32(address),
+            abi.interrupt_vector + LINE as u32 * Word::SIZE,
+            Word::WIDTH,
+        )
+        .unwrap();
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(3)));
+    assert_eq!(cpu.registers().read(Register::new(11)).to_u32(), 3);
+    assert!((300..400).contains(&cpu.cycles()), "{}", cpu.cycles());
+}
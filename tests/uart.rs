@@ -0,0 +1,93 @@
+mod common;
+
+use std::sync::mpsc;
+
+use proxy::{Abi, Device, Stop, Uart, Word};
+
+fn read(uart: &mut Uart, offset: u32) -> u32 {
+    uart.read(offset, Word::WIDTH).to_u32()
+}
+
+#[test]
+fn programs_echo_input() {
+    // copies input to output until input is closed, exits with the byte count
+    let source = "
+        const 4026531840u eax
+        const 4294967295u edx
+        const 1u ebx
+        const loop ecx
+        const 8192u %10
+        addi ecx %10 ecx
+        const done %11
+        addi %11 %10 %11
+        loop:
+        const 8u %12
+        addi eax %12 %12
+        load %12 %12 4
+        beq %11 %12 edx
+        store %12 eax 1
+        addi %13 ebx %13
+        jmp ecx
+        done:
+        exit %13
+    ";
+
+    let (uart, output) = Uart::buffered(b"hello\n");
+
+    let mut cpu = common::load(source);
+    cpu.map_device(Abi::default().device_memory, Uart::SIZE, uart);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(6)));
+    assert_eq!(output.contents(), b"hello\n");
+}
+
+#[test]
+fn reads_dont_block_without_input() {
+    let (sender, receiver) = mpsc::channel();
+    let mut uart = Uart::new(receiver, Vec::new());
+
+    assert_eq!(read(&mut uart, Uart::STATUS), 0);
+    assert_eq!(read(&mut uart, Uart::DATA), Uart::EMPTY);
+
+    sender.send(b'a').unwrap();
+    sender.send(b'b').unwrap();
+
+    assert_eq!(read(&mut uart, Uart::STATUS), Uart::AVAILABLE);
+    assert_eq!(read(&mut uart, Uart::DATA), b'a' as u32);
+    assert_eq!(read(&mut uart, Uart::BLOCKING_DATA), b'b' as u32);
+
+    drop(sender);
+
+    assert_eq!(read(&mut uart, Uart::STATUS), Uart::CLOSED);
+    assert_eq!(read(&mut uart, Uart::BLOCKING_DATA), Uart::EMPTY);
+}
+
+#[test]
+fn status_doesnt_consume_input() {
+    let (mut uart, _) = Uart::buffered(b"x");
+
+    assert_eq!(read(&mut uart, Uart::STATUS), Uart::AVAILABLE);
+    assert_eq!(read(&mut uart, Uart::STATUS), Uart::AVAILABLE);
+    assert_eq!(read(&mut uart, Uart::DATA), b'x' as u32);
+    assert_eq!(read(&mut uart, Uart::STATUS), Uart::CLOSED);
+}
+
+#[test]
+fn writes_output_the_low_byte() {
+    let (mut uart, output) = Uart::buffered(b"");
+
+    uart.write(Uart::DATA, Word::from_u32(0x1234_5641), Word::WIDTH);
+    uart.write(Uart::STATUS, Word::from_u32(b'!' as u32), Word::WIDTH);
+
+    assert_eq!(output.contents(), b"A");
+}
+
+#[test]
+fn narrow_reads_return_the_low_byte() {
+    let (mut uart, _) = Uart::buffered(b"x");
+
+    assert_eq!(uart.read(Uart::STATUS, 1).to_u32(), Uart::AVAILABLE);
+    assert_eq!(uart.read(Uart::DATA, 2).to_u32(), b'x' as u32);
+    assert_eq!(uart.read(Uart::DATA, 1).to_u32(), 0xff);
+    assert_eq!(uart.read(Uart::DATA, 2).to_u32(), 0xffff);
+}
@@ -0,0 +1,216 @@
+use std::{fs, io::SeekFrom, path::PathBuf, process};
+
+use proxy::{FileError, Files, MemoryFs, Mount, OpenFlags, Vfs};
+
+/// Creates a host root holding the file `a`, with the file `secret` next to it.
+fn dir(name: &str) -> PathBuf {
+    let parent = std::env::temp_dir().join(format!("proxy-vfs-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&parent);
+
+    let root = parent.join("root");
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("a"), "a").unwrap();
+    fs::write(parent.join("secret"), "secret").unwrap();
+
+    root
+}
+
+/// A vfs with `mount` at the root.
+fn mounted(mount: Mount) -> Vfs {
+    let mut vfs = Vfs::new();
+    vfs.mount("/", mount);
+
+    vfs
+}
+
+#[test]
+fn dot_dot_cant_leave_the_root() {
+    let root = dir("dot-dot");
+    let vfs = mounted(Mount::host(&root));
+
+    assert_eq!(vfs.read("/../secret"), Err(FileError::NotFound));
+    assert_eq!(vfs.read("../../secret"), Err(FileError::NotFound));
+    assert_eq!(vfs.read("/../a").unwrap(), b"a");
+}
+
+#[test]
+fn absolute_paths_are_in_the_root() {
+    let root = dir("absolute");
+    let vfs = mounted(Mount::host(&root));
+
+    let secret = root.parent().unwrap().join("secret");
+
+    assert_eq!(vfs.read("/a").unwrap(), b"a");
+    assert_eq!(vfs.read("a").unwrap(), b"a");
+    assert_eq!(vfs.read(secret.to_str().unwrap()), Err(FileError::NotFound));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_cant_leave_host_mounts() {
+    use std::os::unix::fs::symlink;
+
+    let root = dir("symlink");
+    symlink(root.parent().unwrap().join("secret"), root.join("out")).unwrap();
+    symlink(root.parent().unwrap(), root.join("parent")).unwrap();
+    symlink(root.join("a"), root.join("in")).unwrap();
+
+    let mut files = Files::new(mounted(Mount::host(&root)));
+
+    assert_eq!(files.vfs().read("/out"), Err(FileError::PermissionDenied));
+    assert_eq!(
+        files.vfs().read("/parent/secret"),
+        Err(FileError::PermissionDenied)
+    );
+    assert_eq!(
+        files.open("/parent/new", OpenFlags::WRITE | OpenFlags::CREATE),
+        Err(FileError::PermissionDenied)
+    );
+    assert!(!root.parent().unwrap().join("new").exists());
+
+    assert_eq!(files.vfs().read("/in").unwrap(), b"a");
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_cant_be_created() {
+    use std::os::unix::fs::symlink;
+
+    let root = dir("dangling");
+    let outside = root.parent().unwrap().join("created");
+    symlink(&outside, root.join("out")).unwrap();
+    symlink(root.join("missing"), root.join("in")).unwrap();
+
+    let mut files = Files::new(mounted(Mount::host(&root)));
+
+    for path in ["/out", "/in"] {
+        assert_eq!(
+            files.open(path, OpenFlags::WRITE | OpenFlags::CREATE),
+            Err(FileError::PermissionDenied),
+            "{}",
+            path
+        );
+    }
+
+    assert!(!outside.exists());
+    assert!(!root.join("missing").exists());
+}
+
+#[test]
+fn read_only_mounts_cant_be_written() {
+    let root = dir("read-only");
+    let mut files = Files::new(mounted(Mount::host_read_only(&root)));
+
+    for flags in [
+        OpenFlags::WRITE,
+        OpenFlags::APPEND,
+        OpenFlags::READ | OpenFlags::TRUNCATE,
+        OpenFlags::WRITE | OpenFlags::CREATE,
+    ] {
+        assert_eq!(files.open("/a", flags), Err(FileError::PermissionDenied));
+    }
+
+    assert_eq!(
+        files.open("/b", OpenFlags::WRITE | OpenFlags::CREATE),
+        Err(FileError::PermissionDenied)
+    );
+    assert!(!root.join("b").exists());
+
+    assert!(files.open("/a", OpenFlags::READ).is_ok());
+}
+
+#[test]
+fn the_longest_mount_is_used() {
+    let outer = MemoryFs::new();
+    outer.insert("data/x", "outer");
+    outer.insert("database/x", "outer");
+
+    let inner = MemoryFs::new();
+    inner.insert("x", "inner");
+
+    let mut vfs = Vfs::new();
+    vfs.mount("/data", Mount::Memory(inner));
+    vfs.mount("/", Mount::Memory(outer));
+
+    assert_eq!(vfs.read("/data/x").unwrap(), b"inner");
+    assert_eq!(vfs.read("/data/../data/./x").unwrap(), b"inner");
+
+    // mounts match whole components
+    assert_eq!(vfs.read("/database/x").unwrap(), b"outer");
+
+    // paths outside of every mount don't exist
+    let mut vfs = Vfs::new();
+    vfs.mount("/data", Mount::Memory(MemoryFs::new()));
+
+    assert_eq!(vfs.read("/x"), Err(FileError::NotFound));
+}
+
+#[test]
+fn memory_files_have_a_maximum_size() {
+    let fs = MemoryFs::new();
+
+    let mut vfs = Vfs::new();
+    vfs.mount("/", Mount::Memory(fs.clone()));
+
+    let mut files = Files::new(vfs);
+    let file = files
+        .open("/big", OpenFlags::WRITE | OpenFlags::CREATE)
+        .unwrap();
+
+    let last = MemoryFs::MAX_FILE_SIZE - 1;
+    files.seek(file, SeekFrom::Start(last)).unwrap();
+
+    assert_eq!(files.write(file, b"ab"), Err(FileError::Io));
+    assert_eq!(files.write(file, b"a"), Ok(1));
+    assert_eq!(files.write(file, b"b"), Err(FileError::Io));
+
+    files.seek(file, SeekFrom::Start(u64::MAX)).unwrap();
+    assert_eq!(files.write(file, b"a"), Err(FileError::Io));
+
+    assert_eq!(fs.get("big").unwrap().len() as u64, MemoryFs::MAX_FILE_SIZE);
+}
+
+#[test]
+fn host_and_memory_mounts_open_alike() {
+    let root = dir("flags");
+    let fs = MemoryFs::new();
+    fs.insert("a", "a");
+
+    let flags = [
+        OpenFlags(0),
+        OpenFlags::CREATE,
+        OpenFlags::READ | OpenFlags::CREATE,
+        OpenFlags::APPEND | OpenFlags::CREATE,
+        OpenFlags::WRITE | OpenFlags::CREATE,
+        OpenFlags::READ | OpenFlags::TRUNCATE,
+        OpenFlags::APPEND | OpenFlags::TRUNCATE,
+    ];
+
+    for mount in [Mount::host(&root), Mount::Memory(fs.clone())] {
+        let mut files = Files::new(mounted(mount));
+
+        let results = flags.map(|flags| {
+            let opened = files.open("/b", flags).is_ok();
+            let _ = files.open("/a", flags);
+
+            // contents of `a` and whether `b` was created
+            let a = files.vfs().read("/a").unwrap();
+            let b = files.vfs().read("/b").is_ok();
+
+            (opened, a, b)
+        });
+
+        assert_eq!(
+            results,
+            [
+                (false, b"a".to_vec(), false),
+                (false, b"a".to_vec(), false),
+                (false, b"a".to_vec(), false),
+                (true, b"a".to_vec(), true),
+                (true, b"a".to_vec(), true),
+                (true, b"a".to_vec(), true),
+                (true, b"".to_vec(), true),
+            ]
+        );
+    }
+}
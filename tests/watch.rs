@@ -0,0 +1,91 @@
+mod common;
+
+use common::{write, DATA};
+use proxy::{Abi, Access, Cpu, Register, Stop, WatchHit, WatchKind, Word};
+
+/// Reads the word at `DATA`, then writes `0x11223344` over it.
+const SOURCE: &str = "
+    const 32768u ebx
+    load ebx ecx 4
+    const 287454020u eax
+    store eax ebx 4
+    exit eax
+";
+
+fn load() -> Cpu<()> {
+    let mut cpu = common::load(SOURCE);
+    write(&mut cpu, DATA, 0xaabbccdd);
+
+    cpu
+}
+
+/// Address of the instruction at `index`, counting constants as two.
+fn eip(index: u32) -> u32 {
+    Abi::default().system_memory + index * Word::SIZE
+}
+
+#[test]
+fn writes_stop_with_old_and_new_values() {
+    let mut cpu = load();
+    cpu.add_watchpoint(DATA + 2, 2, WatchKind::Write);
+
+    let hit = WatchHit {
+        eip: eip(5),
+        address: DATA + 2,
+        len: 2,
+        access: Access::Write,
+        old: Word::from_u32(0xccdd),
+        new: Word::from_u32(0x3344),
+    };
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Watchpoint(hit)));
+
+    // the write completed and running again continues after it
+    assert_eq!(cpu.memory().read(DATA, 4).unwrap().to_u32(), 0x11223344);
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(0x11223344)));
+}
+
+#[test]
+fn reads_stop_only_read_watchpoints() {
+    let mut cpu = load();
+    cpu.add_watchpoint(DATA, 1, WatchKind::Read);
+
+    let Ok(Stop::Watchpoint(hit)) = cpu.run(&mut ()) else {
+        panic!("expected a watchpoint");
+    };
+
+    assert_eq!((hit.eip, hit.access), (eip(2), Access::Read));
+    assert_eq!(hit.old, hit.new);
+    assert_eq!(hit.new.to_u32(), 0xaa);
+    assert_eq!(cpu.registers().read(Register::ECX).to_u32(), 0xaabbccdd);
+
+    // the write isn't watched
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(0x11223344)));
+}
+
+#[test]
+fn read_write_watchpoints_stop_on_both() {
+    let mut cpu = load();
+    cpu.add_watchpoint(DATA, 4, WatchKind::ReadWrite);
+
+    let accesses = [cpu.run(&mut ()), cpu.run(&mut ())].map(|stop| match stop {
+        Ok(Stop::Watchpoint(hit)) => hit.access,
+        stop => panic!("expected a watchpoint, found {:?}", stop),
+    });
+
+    assert_eq!(accesses, [Access::Read, Access::Write]);
+}
+
+#[test]
+fn removed_watchpoints_dont_stop() {
+    let mut cpu = load();
+
+    let id = cpu.add_watchpoint(DATA, 4, WatchKind::ReadWrite);
+    cpu.add_watchpoint(DATA + 4, 4, WatchKind::ReadWrite);
+
+    assert!(cpu.remove_watchpoint(id).is_some());
+    assert!(cpu.remove_watchpoint(id).is_none());
+    assert_eq!(cpu.watchpoints().count(), 1);
+
+    assert_eq!(cpu.run(&mut ()), Ok(Stop::Exit(0x11223344)));
+}